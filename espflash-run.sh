@@ -0,0 +1,18 @@
+#!/bin/bash
+set -euo pipefail
+
+# Check for the binary argument
+if [ -z "${1:-}" ]
+  then
+    echo "No binary argument supplied"
+    exit 1
+fi
+
+# esp-idf-sys puts the bootloader it built next to the binary. Flashing it with the custom
+# partition table keeps the rollback support and the NVS layout the image was built for,
+# and erasing otadata boots the flashed image instead of the last OTA slot
+espflash flash --monitor \
+  --bootloader "$(dirname "$1")/bootloader.bin" \
+  --partition-table partitions.csv \
+  --erase-parts otadata \
+  "$1"
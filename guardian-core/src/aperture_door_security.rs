@@ -0,0 +1,1208 @@
+use std::fmt::Debug;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::guardian_hal::{InputPin, OutputPin, SharedClock};
+use super::guardian_reader_feedback::ReaderFeedback;
+use super::manage_command::{CommandOutcome, CommandRequest, MANAGECommand, MANAGEReport};
+
+pub enum DoorSecurityDoorType {
+    Motorized(MotorizedDoorConfig),
+    LockFailSecure,
+}
+
+pub struct MotorizedDoorConfig {
+    // How long the open/close/stop outputs are held high per command
+    pub pulse_duration: Duration,
+    // How long the door may take to fully open or close
+    pub travel_timeout: Duration,
+    // Whether the limit switches read high when the door reached them
+    pub limit_switch_active_high: bool,
+}
+
+pub struct DoorInputConfig {
+    // How long an input must be stable before a change is accepted
+    pub debounce: Duration,
+    // Whether the door position sensor reads high while the door is open
+    pub door_position_active_high: bool,
+    // Whether the request-to-exit button reads high while pressed
+    pub request_to_exit_active_high: bool,
+    // How long the door may stay open before it is reported as held open
+    pub held_open_timeout: Duration,
+    // How long a request-to-exit unlocks the door for, in seconds
+    pub request_to_exit_unlock_duration: u32,
+}
+
+// Outputs driving the lock relay or the motor controller
+pub struct DoorSecurityOutputs<O: OutputPin> {
+    pub open: O,
+    pub close: O,
+    pub stop_unlock: O,
+}
+
+// Optional inputs wired to the door, all of them may be left unfitted
+pub struct DoorSecurityInputs<I: InputPin> {
+    pub open_limit: Option<I>,
+    pub closed_limit: Option<I>,
+    pub door_position: Option<I>,
+    pub request_to_exit: Option<I>,
+}
+
+impl<I: InputPin> DoorSecurityInputs<I> {
+    pub fn none() -> Self {
+        Self {
+            open_limit: None,
+            closed_limit: None,
+            door_position: None,
+            request_to_exit: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MotorizedDoorState {
+    Closed,
+    Opening,
+    Open,
+    Closing,
+    Stopped,
+    Fault,
+}
+
+// Snapshot of a door for the telemetry report
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DoorStatus {
+    pub door_id: u32,
+    // None without a door position sensor
+    pub open: Option<bool>,
+    // The lock is released or the motorized door is opening or open
+    pub unlocked: bool,
+    pub motorized_state: Option<MotorizedDoorState>,
+}
+
+// Input that only reports a change once it has been stable for the debounce time
+struct DebouncedInput<I: InputPin> {
+    pin: I,
+    active_high: bool,
+    active: bool,
+    candidate: bool,
+    candidate_since: Instant,
+}
+
+impl<I: InputPin> DebouncedInput<I> {
+    fn new(pin: I, active_high: bool, now: Instant) -> Self {
+        let active = pin.is_high() == active_high;
+        Self {
+            pin,
+            active_high,
+            active,
+            candidate: active,
+            candidate_since: now,
+        }
+    }
+
+    // Returns the new state if it changed
+    fn update(&mut self, debounce: Duration, now: Instant) -> Option<bool> {
+        let reading = self.pin.is_high() == self.active_high;
+        if reading != self.candidate {
+            self.candidate = reading;
+            self.candidate_since = now;
+        }
+        if self.candidate != self.active
+            && now.saturating_duration_since(self.candidate_since) >= debounce
+        {
+            self.active = self.candidate;
+            return Some(self.active);
+        }
+        None
+    }
+}
+
+pub struct DoorSecurity<O: OutputPin, I: InputPin> {
+    door_id: u32,
+    door_type: DoorSecurityDoorType,
+    door_open_pin: O,
+    door_close_pin: O,
+    door_stop_unlock_pin: O,
+    door_open_limit_pin: Option<I>,
+    door_closed_limit_pin: Option<I>,
+    door_position: Option<DebouncedInput<I>>,
+    request_to_exit: Option<DebouncedInput<I>>,
+    input_config: DoorInputConfig,
+    reader_feedback: ReaderFeedback,
+    reader_released: bool,
+    report_tx: Sender<MANAGEReport>,
+    clock: SharedClock,
+    door_opened_at: Option<Instant>,
+    held_open_reported: bool,
+    last_action_time: Instant,
+    lock_timer: Instant,
+    motorized_state: MotorizedDoorState,
+    travel_started: Instant,
+    auto_close_after: Option<Duration>,
+    auto_close_at: Option<Instant>,
+    output_fault: bool,
+}
+
+impl<O: OutputPin, I: InputPin> DoorSecurity<O, I> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        door_id: u32,
+        door_type: DoorSecurityDoorType,
+        outputs: DoorSecurityOutputs<O>,
+        inputs: DoorSecurityInputs<I>,
+        input_config: DoorInputConfig,
+        reader_feedback: ReaderFeedback,
+        report_tx: Sender<MANAGEReport>,
+        clock: SharedClock,
+    ) -> Self {
+        let now = clock.now();
+        let door_position = inputs
+            .door_position
+            .map(|pin| DebouncedInput::new(pin, input_config.door_position_active_high, now));
+        let request_to_exit = inputs
+            .request_to_exit
+            .map(|pin| DebouncedInput::new(pin, input_config.request_to_exit_active_high, now));
+        let door_opened_at = door_position
+            .as_ref()
+            .filter(|door_position| door_position.active)
+            .map(|_| now);
+
+        let mut door_security = Self {
+            door_id,
+            door_type,
+            door_open_pin: outputs.open,
+            door_close_pin: outputs.close,
+            door_stop_unlock_pin: outputs.stop_unlock,
+            door_open_limit_pin: inputs.open_limit,
+            door_closed_limit_pin: inputs.closed_limit,
+            door_position,
+            request_to_exit,
+            input_config,
+            reader_feedback,
+            reader_released: false,
+            report_tx,
+            clock,
+            door_opened_at,
+            held_open_reported: false,
+            last_action_time: now,
+            lock_timer: now,
+            motorized_state: MotorizedDoorState::Stopped,
+            travel_started: now,
+            auto_close_after: None,
+            auto_close_at: None,
+            output_fault: false,
+        };
+
+        // Derive the initial position from the limit switches, if fitted
+        if let DoorSecurityDoorType::Motorized(_) = door_security.door_type {
+            if door_security.closed_limit_reached() == Some(true) {
+                door_security.set_motorized_state(MotorizedDoorState::Closed);
+            } else if door_security.open_limit_reached() == Some(true) {
+                door_security.set_motorized_state(MotorizedDoorState::Open);
+            } else {
+                // Position unknown until the first full travel
+                door_security.report_motorized_state();
+            }
+        }
+
+        // Start the reader off in its idle state
+        door_security.reader_feedback.door_secured();
+
+        door_security
+    }
+
+    pub fn tick(&mut self) {
+        self.tick_inputs();
+
+        match self.door_type {
+            DoorSecurityDoorType::Motorized(_) => {
+                self.tick_motorized();
+            }
+            DoorSecurityDoorType::LockFailSecure => {
+                self.tick_lock_fail_secure();
+            }
+        }
+    }
+
+    fn tick_motorized(&mut self) {
+        let DoorSecurityDoorType::Motorized(config) = &self.door_type else {
+            return;
+        };
+        let pulse_duration = config.pulse_duration;
+        let travel_timeout = config.travel_timeout;
+
+        // End the command pulse once it has been held long enough
+        if self.elapsed_since(self.last_action_time) > pulse_duration {
+            // Ensure all pins are low, trying every pin even if one fails
+            let released = [
+                self.door_open_pin.set_low(),
+                self.door_close_pin.set_low(),
+                self.door_stop_unlock_pin.set_low(),
+            ];
+            self.tick_output(released.into_iter().collect());
+        }
+
+        // Both limit switches active at once means a wiring or switch fault
+        if self.open_limit_reached() == Some(true) && self.closed_limit_reached() == Some(true) {
+            if self.motorized_state != MotorizedDoorState::Fault {
+                log::error!("DOOR FAULT - Both limit switches are active!");
+                self.set_motorized_state(MotorizedDoorState::Fault);
+            }
+            return;
+        }
+
+        // Track travel towards the end positions
+        let travel_elapsed = self.elapsed_since(self.travel_started);
+        match self.motorized_state {
+            MotorizedDoorState::Opening => match self.open_limit_reached() {
+                Some(true) => self.set_motorized_state(MotorizedDoorState::Open),
+                Some(false) if travel_elapsed > travel_timeout => {
+                    log::error!("DOOR FAULT - Door did not reach the open limit in time!");
+                    self.set_motorized_state(MotorizedDoorState::Fault);
+                }
+                // Without a limit switch assume the door is open after the travel time
+                None if travel_elapsed > travel_timeout => {
+                    self.set_motorized_state(MotorizedDoorState::Open)
+                }
+                _ => {}
+            },
+            MotorizedDoorState::Closing => match self.closed_limit_reached() {
+                Some(true) => self.set_motorized_state(MotorizedDoorState::Closed),
+                Some(false) if travel_elapsed > travel_timeout => {
+                    log::error!("DOOR FAULT - Door did not reach the closed limit in time!");
+                    self.set_motorized_state(MotorizedDoorState::Fault);
+                }
+                // Without a limit switch assume the door is closed after the travel time
+                None if travel_elapsed > travel_timeout => {
+                    self.set_motorized_state(MotorizedDoorState::Closed)
+                }
+                _ => {}
+            },
+            MotorizedDoorState::Open => {
+                // Close again once a timed unlock has elapsed
+                if let Some(auto_close_at) = self.auto_close_at {
+                    if auto_close_at < self.clock.now() {
+                        log::info!("DOOR ACTION - Timed opening elapsed!");
+                        let closed = self.close();
+                        self.tick_output(closed);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn tick_inputs(&mut self) {
+        let debounce = self.input_config.debounce;
+        let now = self.clock.now();
+
+        // Request-to-exit unlocks the door from the inside
+        let request_to_exit = self
+            .request_to_exit
+            .as_mut()
+            .and_then(|request_to_exit| request_to_exit.update(debounce, now));
+        if request_to_exit == Some(true) {
+            log::info!("DOOR INPUT - Request to exit!");
+            self.report(MANAGEReport::DoorRequestToExit {
+                door_id: self.door_id,
+            });
+            self.handle_command(MANAGECommand::DoorUnlock {
+                door_id: self.door_id,
+                duration: self.input_config.request_to_exit_unlock_duration,
+            });
+        }
+
+        // Track the door position sensor
+        let door_position = self
+            .door_position
+            .as_mut()
+            .and_then(|door_position| door_position.update(debounce, now));
+        match door_position {
+            Some(true) => {
+                log::info!("DOOR INPUT - Door opened!");
+                self.door_opened_at = Some(now);
+                self.held_open_reported = false;
+                self.report(MANAGEReport::DoorOpened {
+                    door_id: self.door_id,
+                });
+
+                // Opening the door while it is secured means it was forced
+                if !self.opening_authorized() {
+                    log::error!("DOOR ALARM - Door forced open!");
+                    self.report(MANAGEReport::DoorForcedOpen {
+                        door_id: self.door_id,
+                    });
+                }
+            }
+            Some(false) => {
+                log::info!("DOOR INPUT - Door closed!");
+                self.door_opened_at = None;
+                self.report(MANAGEReport::DoorClosed {
+                    door_id: self.door_id,
+                });
+            }
+            None => {}
+        }
+
+        // Alarm once if the door stays open for too long
+        if let Some(door_opened_at) = self.door_opened_at {
+            let open_duration = self.elapsed_since(door_opened_at);
+            if !self.held_open_reported
+                && open_duration > self.input_config.held_open_timeout
+                && self.held_open_alarm_applies()
+            {
+                log::error!("DOOR ALARM - Door held open!");
+                self.held_open_reported = true;
+                self.report(MANAGEReport::DoorHeldOpen {
+                    door_id: self.door_id,
+                    open_seconds: open_duration.as_secs() as u32,
+                });
+            }
+        }
+    }
+
+    pub fn status(&self) -> DoorStatus {
+        DoorStatus {
+            door_id: self.door_id,
+            open: self
+                .door_position
+                .as_ref()
+                .map(|door_position| door_position.active),
+            unlocked: self.opening_authorized(),
+            motorized_state: match self.door_type {
+                DoorSecurityDoorType::Motorized(_) => Some(self.motorized_state),
+                DoorSecurityDoorType::LockFailSecure => None,
+            },
+        }
+    }
+
+    fn opening_authorized(&self) -> bool {
+        match self.door_type {
+            // A motorized door may only open when told to, not while stopped or faulted
+            DoorSecurityDoorType::Motorized(_) => matches!(
+                self.motorized_state,
+                MotorizedDoorState::Opening | MotorizedDoorState::Open
+            ),
+            // A lock may only be opened while it is released
+            DoorSecurityDoorType::LockFailSecure => self.lock_timer > self.clock.now(),
+        }
+    }
+
+    fn held_open_alarm_applies(&self) -> bool {
+        match self.door_type {
+            // A motorized door commanded open is expected to stay open
+            DoorSecurityDoorType::Motorized(_) => !matches!(
+                self.motorized_state,
+                MotorizedDoorState::Opening | MotorizedDoorState::Open
+            ),
+            DoorSecurityDoorType::LockFailSecure => true,
+        }
+    }
+
+    fn tick_lock_fail_secure(&mut self) {
+        // Check if the lock should be released
+        if self.lock_timer < self.clock.now() {
+            let locked = self.door_stop_unlock_pin.set_low();
+            self.tick_output(locked);
+            self.reader_secured();
+        }
+    }
+
+    // An output that can't be driven from the tick is reported once and retried on every
+    // tick, the door keeps running
+    fn tick_output(&mut self, result: Result<(), O::Error>) {
+        match result {
+            Ok(()) => {
+                if self.output_fault {
+                    log::info!("DOOR - Outputs can be driven again");
+                    self.output_fault = false;
+                }
+            }
+            Err(error) => {
+                if !self.output_fault {
+                    log::error!("DOOR FAULT - Failed to drive output: {:?}", error);
+                    self.output_fault = true;
+                    self.report(MANAGEReport::DoorFault {
+                        door_id: self.door_id,
+                        error: format!("Failed to drive output: {:?}", error),
+                    });
+                }
+            }
+        }
+    }
+
+    // Carry out a command, reporting the result if MANAGE is waiting for it
+    pub fn handle_request(&mut self, request: CommandRequest) {
+        let result = self.handle_command(request.command);
+        if let Some(request_id) = request.request_id {
+            self.report(MANAGEReport::CommandResult { request_id, result });
+        }
+    }
+
+    pub fn handle_command(&mut self, command: MANAGECommand) -> CommandOutcome {
+        match self.execute(command) {
+            Ok(()) => CommandOutcome::Success,
+            Err(outcome) => outcome,
+        }
+    }
+
+    fn execute(&mut self, command: MANAGECommand) -> Result<(), CommandOutcome> {
+        match command {
+            MANAGECommand::DoorOpen { .. } => {
+                log::info!("DOOR ACTION - Opening the door!");
+
+                // Update the last action time
+                self.last_action_time = self.clock.now();
+
+                // Ensure all other pins are low
+                self.door_close_pin.set_low().map_err(output_error)?;
+                self.door_stop_unlock_pin.set_low().map_err(output_error)?;
+
+                // Set the door open pin high
+                self.door_open_pin.set_high().map_err(output_error)?;
+
+                // Track the travel of a motorized door
+                self.auto_close_after = None;
+                self.start_travel(MotorizedDoorState::Opening);
+            }
+            MANAGECommand::DoorClose { .. } => {
+                log::info!("DOOR ACTION - Closing the door!");
+                self.close().map_err(output_error)?;
+            }
+            MANAGECommand::DoorStop { .. } => {
+                log::info!("DOOR ACTION - ***STOPPING*** the door!");
+
+                // Update the last action time
+                self.last_action_time = self.clock.now();
+
+                // Ensure all other pins are low
+                self.door_open_pin.set_low().map_err(output_error)?;
+                self.door_close_pin.set_low().map_err(output_error)?;
+
+                // Set the door stop pin high
+                self.door_stop_unlock_pin.set_high().map_err(output_error)?;
+
+                // A stopped door is no longer travelling or waiting to close
+                self.auto_close_after = None;
+                self.auto_close_at = None;
+                if matches!(
+                    self.motorized_state,
+                    MotorizedDoorState::Opening | MotorizedDoorState::Closing
+                ) {
+                    self.set_motorized_state(MotorizedDoorState::Stopped);
+                }
+            }
+            MANAGECommand::DoorUnlock { duration, .. } => match self.door_type {
+                DoorSecurityDoorType::Motorized(_) => {
+                    log::info!("DOOR ACTION - Opening the door for {} seconds!", duration);
+
+                    // Open the door and close it again once the duration elapsed
+                    let open_duration = Duration::from_secs(duration as u64);
+                    if self.motorized_state == MotorizedDoorState::Open {
+                        self.auto_close_at = Some(self.clock.now() + open_duration);
+                    } else {
+                        self.execute(MANAGECommand::DoorOpen {
+                            door_id: self.door_id,
+                        })?;
+                        self.auto_close_after = Some(open_duration);
+                    }
+                }
+                DoorSecurityDoorType::LockFailSecure => {
+                    log::info!("DOOR ACTION - Unlocking the door for {} seconds!", duration);
+
+                    // Set the door unlock pin high
+                    self.door_stop_unlock_pin.set_high().map_err(output_error)?;
+
+                    // Update the last action time once the lock has actually released
+                    self.lock_timer = self.clock.now() + Duration::from_secs(duration as u64);
+
+                    // Show the door as unlocked on the reader for exactly as long
+                    self.reader_released = true;
+                    self.reader_feedback
+                        .door_released(Some(Duration::from_secs(duration as u64)));
+                }
+            },
+            _ => {
+                log::warn!("DOOR ACTION - Ignoring non-door command: {:?}", command);
+                return Err(CommandOutcome::Rejected {
+                    reason: "Not a door command".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), O::Error> {
+        // Update the last action time
+        self.last_action_time = self.clock.now();
+
+        // Ensure all other pins are low
+        self.door_open_pin.set_low()?;
+        self.door_stop_unlock_pin.set_low()?;
+
+        // Set the door close pin high
+        self.door_close_pin.set_high()?;
+
+        // Track the travel of a motorized door
+        self.auto_close_after = None;
+        self.start_travel(MotorizedDoorState::Closing);
+        Ok(())
+    }
+
+    fn start_travel(&mut self, state: MotorizedDoorState) {
+        if !matches!(self.door_type, DoorSecurityDoorType::Motorized(_)) {
+            return;
+        }
+
+        // Nothing to do if the door already is where it is going
+        let target = match state {
+            MotorizedDoorState::Opening => MotorizedDoorState::Open,
+            _ => MotorizedDoorState::Closed,
+        };
+        self.auto_close_at = None;
+        if self.motorized_state != target {
+            self.travel_started = self.clock.now();
+            self.set_motorized_state(state);
+        }
+    }
+
+    fn set_motorized_state(&mut self, state: MotorizedDoorState) {
+        if self.motorized_state == state {
+            return;
+        }
+        self.motorized_state = state;
+
+        // Show the door as released on the reader while it is opening or open
+        match state {
+            MotorizedDoorState::Opening | MotorizedDoorState::Open => {
+                if !self.reader_released {
+                    self.reader_released = true;
+                    self.reader_feedback.door_released(None);
+                }
+            }
+            _ => self.reader_secured(),
+        }
+
+        // Schedule the timed close once the door is fully open
+        if state == MotorizedDoorState::Open {
+            self.auto_close_at = self
+                .auto_close_after
+                .take()
+                .map(|auto_close_after| self.clock.now() + auto_close_after);
+        }
+
+        self.report_motorized_state();
+    }
+
+    // Return the reader to idle once the door that was released is secured again
+    fn reader_secured(&mut self) {
+        if self.reader_released {
+            self.reader_released = false;
+            self.reader_feedback.door_secured();
+        }
+    }
+
+    fn report_motorized_state(&self) {
+        log::info!("DOOR STATE - {:?}", self.motorized_state);
+        self.report(MANAGEReport::DoorState {
+            door_id: self.door_id,
+            state: self.motorized_state,
+        });
+    }
+
+    fn elapsed_since(&self, since: Instant) -> Duration {
+        self.clock.now().saturating_duration_since(since)
+    }
+
+    fn report(&self, report: MANAGEReport) {
+        self.report_tx.send(report).unwrap();
+    }
+
+    fn limit_switch_active_high(&self) -> bool {
+        match &self.door_type {
+            DoorSecurityDoorType::Motorized(config) => config.limit_switch_active_high,
+            DoorSecurityDoorType::LockFailSecure => true,
+        }
+    }
+
+    fn open_limit_reached(&self) -> Option<bool> {
+        let active_high = self.limit_switch_active_high();
+        self.door_open_limit_pin
+            .as_ref()
+            .map(|pin| pin.is_high() == active_high)
+    }
+
+    fn closed_limit_reached(&self) -> Option<bool> {
+        let active_high = self.limit_switch_active_high();
+        self.door_closed_limit_pin
+            .as_ref()
+            .map(|pin| pin.is_high() == active_high)
+    }
+}
+
+// A relay that can't be driven leaves the door in an unknown state
+fn output_error<E: Debug>(error: E) -> CommandOutcome {
+    log::error!("DOOR FAULT - Failed to drive output: {:?}", error);
+    CommandOutcome::HardwareError {
+        error: format!("Failed to drive output: {:?}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::guardian_hal::fake::{FakeInputPin, FakeOutputPin, ManualClock};
+    use crate::guardian_reader_control::ReaderLedColor;
+    use crate::guardian_reader_feedback::ReaderFeedbackPolicy;
+
+    const INPUT_CONFIG: DoorInputConfig = DoorInputConfig {
+        debounce: Duration::from_millis(200),
+        door_position_active_high: true,
+        request_to_exit_active_high: false,
+        held_open_timeout: Duration::from_secs(30),
+        request_to_exit_unlock_duration: 5,
+    };
+
+    struct Fixture {
+        door: DoorSecurity<FakeOutputPin, FakeInputPin>,
+        open: FakeOutputPin,
+        close: FakeOutputPin,
+        stop_unlock: FakeOutputPin,
+        clock: ManualClock,
+        reports: Receiver<MANAGEReport>,
+        reader: Receiver<CommandRequest>,
+    }
+
+    impl Fixture {
+        fn new(door_type: DoorSecurityDoorType, inputs: DoorSecurityInputs<FakeInputPin>) -> Self {
+            let outputs = DoorSecurityOutputs {
+                open: FakeOutputPin::default(),
+                close: FakeOutputPin::default(),
+                stop_unlock: FakeOutputPin::default(),
+            };
+            let (open, close, stop_unlock) = (
+                outputs.open.clone(),
+                outputs.close.clone(),
+                outputs.stop_unlock.clone(),
+            );
+            let clock = ManualClock::new();
+            let (report_tx, reports) = channel();
+            let (reader_command_tx, reader) = channel();
+            let door = DoorSecurity::new(
+                0,
+                door_type,
+                outputs,
+                inputs,
+                INPUT_CONFIG,
+                ReaderFeedback::new(ReaderFeedbackPolicy::default(), vec![0], reader_command_tx),
+                report_tx,
+                Arc::new(clock.clone()),
+            );
+            Self {
+                door,
+                open,
+                close,
+                stop_unlock,
+                clock,
+                reports,
+                reader,
+            }
+        }
+
+        fn advance(&mut self, duration: Duration) {
+            self.clock.advance(duration);
+            self.door.tick();
+        }
+
+        fn reports(&self) -> Vec<MANAGEReport> {
+            self.reports.try_iter().collect()
+        }
+
+        // LED colours and durations sent to the reader so far
+        fn reader_leds(&self) -> Vec<(ReaderLedColor, u32)> {
+            self.reader
+                .try_iter()
+                .filter_map(|request| match request.command {
+                    MANAGECommand::ReaderLed {
+                        color, duration_ms, ..
+                    } => Some((color, duration_ms)),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    fn motorized() -> DoorSecurityDoorType {
+        DoorSecurityDoorType::Motorized(MotorizedDoorConfig {
+            pulse_duration: Duration::from_millis(500),
+            travel_timeout: Duration::from_secs(10),
+            limit_switch_active_high: true,
+        })
+    }
+
+    fn states(reports: &[MANAGEReport]) -> Vec<MotorizedDoorState> {
+        reports
+            .iter()
+            .filter_map(|report| match report {
+                MANAGEReport::DoorState { state, .. } => Some(*state),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lock_fail_secure_relocks_after_duration() {
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs::none(),
+        );
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        });
+        assert!(fixture.stop_unlock.is_high());
+
+        fixture.advance(Duration::from_secs(4));
+        assert!(fixture.stop_unlock.is_high());
+
+        fixture.advance(Duration::from_secs(2));
+        assert!(!fixture.stop_unlock.is_high());
+    }
+
+    #[test]
+    fn broken_relay_is_reported_once_without_stopping_the_door() {
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs::none(),
+        );
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 1,
+        });
+        fixture.stop_unlock.break_output();
+
+        fixture.advance(Duration::from_secs(2));
+        fixture.advance(Duration::from_secs(1));
+        let faults: Vec<_> = fixture
+            .reports()
+            .into_iter()
+            .filter(|report| matches!(report, MANAGEReport::DoorFault { door_id: 0, .. }))
+            .collect();
+        assert_eq!(faults.len(), 1);
+    }
+
+    #[test]
+    fn motorized_without_limit_switches_assumes_travel_time() {
+        let mut fixture = Fixture::new(motorized(), DoorSecurityInputs::none());
+        assert_eq!(states(&fixture.reports()), [MotorizedDoorState::Stopped]);
+
+        fixture
+            .door
+            .handle_command(MANAGECommand::DoorOpen { door_id: 0 });
+        assert!(fixture.open.is_high());
+        assert_eq!(states(&fixture.reports()), [MotorizedDoorState::Opening]);
+
+        // The command pulse ends before the door arrives
+        fixture.advance(Duration::from_millis(600));
+        assert!(!fixture.open.is_high());
+        assert!(fixture.reports().is_empty());
+
+        fixture.advance(Duration::from_secs(10));
+        assert_eq!(states(&fixture.reports()), [MotorizedDoorState::Open]);
+    }
+
+    #[test]
+    fn motorized_unlock_closes_again_after_duration() {
+        let mut fixture = Fixture::new(motorized(), DoorSecurityInputs::none());
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 3,
+        });
+        fixture.advance(Duration::from_secs(11));
+        fixture.reports();
+
+        fixture.advance(Duration::from_secs(2));
+        assert!(fixture.reports().is_empty());
+
+        fixture.advance(Duration::from_secs(2));
+        assert!(fixture.close.is_high());
+        assert_eq!(states(&fixture.reports()), [MotorizedDoorState::Closing]);
+    }
+
+    #[test]
+    fn motorized_timed_close_failure_is_reported() {
+        let mut fixture = Fixture::new(motorized(), DoorSecurityInputs::none());
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 3,
+        });
+        fixture.advance(Duration::from_secs(11));
+        fixture.reports();
+
+        fixture.close.stick_low();
+        fixture.advance(Duration::from_secs(4));
+        let faults: Vec<_> = fixture
+            .reports()
+            .into_iter()
+            .filter(|report| matches!(report, MANAGEReport::DoorFault { door_id: 0, .. }))
+            .collect();
+        assert_eq!(faults.len(), 1);
+    }
+
+    #[test]
+    fn motorized_limit_switches_track_position() {
+        let open_limit = FakeInputPin::default();
+        let closed_limit = FakeInputPin::default();
+        closed_limit.set(true);
+        let mut fixture = Fixture::new(
+            motorized(),
+            DoorSecurityInputs {
+                open_limit: Some(open_limit.clone()),
+                closed_limit: Some(closed_limit.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        assert_eq!(states(&fixture.reports()), [MotorizedDoorState::Closed]);
+
+        fixture
+            .door
+            .handle_command(MANAGECommand::DoorOpen { door_id: 0 });
+        closed_limit.set(false);
+        fixture.advance(Duration::from_secs(3));
+        open_limit.set(true);
+        fixture.advance(Duration::from_millis(100));
+        assert_eq!(
+            states(&fixture.reports()),
+            [MotorizedDoorState::Opening, MotorizedDoorState::Open]
+        );
+    }
+
+    #[test]
+    fn motorized_faults_when_limit_not_reached() {
+        let closed_limit = FakeInputPin::default();
+        closed_limit.set(true);
+        let mut fixture = Fixture::new(
+            motorized(),
+            DoorSecurityInputs {
+                open_limit: Some(FakeInputPin::default()),
+                closed_limit: Some(closed_limit.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        fixture
+            .door
+            .handle_command(MANAGECommand::DoorOpen { door_id: 0 });
+        closed_limit.set(false);
+        fixture.advance(Duration::from_secs(11));
+        assert_eq!(
+            states(&fixture.reports()),
+            [
+                MotorizedDoorState::Closed,
+                MotorizedDoorState::Opening,
+                MotorizedDoorState::Fault
+            ]
+        );
+    }
+
+    #[test]
+    fn door_opened_while_locked_is_forced() {
+        let door_position = FakeInputPin::default();
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs {
+                door_position: Some(door_position.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        door_position.set(true);
+        fixture.advance(Duration::from_millis(100));
+        fixture.advance(Duration::from_millis(250));
+        let reports = fixture.reports();
+        assert!(matches!(
+            reports[..],
+            [
+                MANAGEReport::DoorOpened { .. },
+                MANAGEReport::DoorForcedOpen { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn door_opened_after_failed_unlock_is_forced() {
+        let door_position = FakeInputPin::default();
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs {
+                door_position: Some(door_position.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        fixture.stop_unlock.break_output();
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        });
+        fixture.reports();
+
+        door_position.set(true);
+        fixture.advance(Duration::from_millis(100));
+        fixture.advance(Duration::from_millis(250));
+        assert!(fixture
+            .reports()
+            .iter()
+            .any(|report| matches!(report, MANAGEReport::DoorForcedOpen { door_id: 0 })));
+    }
+
+    #[test]
+    fn door_opened_while_unlocked_is_not_forced() {
+        let door_position = FakeInputPin::default();
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs {
+                door_position: Some(door_position.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        });
+        door_position.set(true);
+        fixture.advance(Duration::from_millis(100));
+        fixture.advance(Duration::from_millis(250));
+        assert!(matches!(
+            fixture.reports()[..],
+            [MANAGEReport::DoorOpened { .. }]
+        ));
+        assert_eq!(
+            fixture.door.status(),
+            DoorStatus {
+                door_id: 0,
+                open: Some(true),
+                unlocked: true,
+                motorized_state: None,
+            }
+        );
+
+        door_position.set(false);
+        fixture.advance(Duration::from_millis(100));
+        fixture.advance(Duration::from_millis(250));
+        assert!(matches!(
+            fixture.reports()[..],
+            [MANAGEReport::DoorClosed { .. }]
+        ));
+    }
+
+    #[test]
+    fn motorized_door_opened_while_stopped_is_forced() {
+        let door_position = FakeInputPin::default();
+        let mut fixture = Fixture::new(
+            motorized(),
+            DoorSecurityInputs {
+                door_position: Some(door_position.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        fixture
+            .door
+            .handle_command(MANAGECommand::DoorOpen { door_id: 0 });
+        fixture
+            .door
+            .handle_command(MANAGECommand::DoorStop { door_id: 0 });
+        fixture.reports();
+        assert!(!fixture.door.status().unlocked);
+
+        door_position.set(true);
+        fixture.advance(Duration::from_millis(100));
+        fixture.advance(Duration::from_millis(250));
+        assert!(matches!(
+            fixture.reports()[..],
+            [
+                MANAGEReport::DoorOpened { .. },
+                MANAGEReport::DoorForcedOpen { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn door_position_glitches_are_debounced() {
+        let door_position = FakeInputPin::default();
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs {
+                door_position: Some(door_position.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        door_position.set(true);
+        fixture.advance(Duration::from_millis(100));
+        door_position.set(false);
+        fixture.advance(Duration::from_millis(150));
+        fixture.advance(Duration::from_millis(150));
+        assert!(fixture.reports().is_empty());
+    }
+
+    #[test]
+    fn door_held_open_is_reported_once() {
+        let door_position = FakeInputPin::default();
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs {
+                door_position: Some(door_position.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        });
+        door_position.set(true);
+        fixture.advance(Duration::from_millis(100));
+        fixture.advance(Duration::from_millis(250));
+        fixture.reports();
+
+        fixture.advance(Duration::from_secs(31));
+        assert!(matches!(
+            fixture.reports()[..],
+            [MANAGEReport::DoorHeldOpen {
+                open_seconds: 31,
+                ..
+            }]
+        ));
+        fixture.advance(Duration::from_secs(31));
+        assert!(fixture.reports().is_empty());
+    }
+
+    #[test]
+    fn request_to_exit_unlocks_the_door() {
+        let request_to_exit = FakeInputPin::default();
+        request_to_exit.set(true);
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs {
+                request_to_exit: Some(request_to_exit.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        request_to_exit.set(false);
+        fixture.advance(Duration::from_millis(100));
+        assert!(!fixture.stop_unlock.is_high());
+
+        fixture.advance(Duration::from_millis(250));
+        assert!(fixture.stop_unlock.is_high());
+        assert!(matches!(
+            fixture.reports()[..],
+            [MANAGEReport::DoorRequestToExit { .. }]
+        ));
+    }
+
+    #[test]
+    fn command_results_are_reported_for_manage_requests() {
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs::none(),
+        );
+        fixture.door.handle_request(CommandRequest {
+            request_id: Some("r1".to_string()),
+            command: MANAGECommand::DoorUnlock {
+                door_id: 0,
+                duration: 5,
+            },
+        });
+        fixture
+            .door
+            .handle_request(CommandRequest::local(MANAGECommand::DoorUnlock {
+                door_id: 0,
+                duration: 5,
+            }));
+
+        fixture.stop_unlock.break_output();
+        fixture.door.handle_request(CommandRequest {
+            request_id: Some("r2".to_string()),
+            command: MANAGECommand::DoorUnlock {
+                door_id: 0,
+                duration: 5,
+            },
+        });
+
+        let results: Vec<_> = fixture
+            .reports()
+            .into_iter()
+            .filter_map(|report| match report {
+                MANAGEReport::CommandResult { request_id, result } => Some((request_id, result)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], ("r1".to_string(), CommandOutcome::Success));
+        assert_eq!(results[1].0, "r2");
+        assert!(matches!(results[1].1, CommandOutcome::HardwareError { .. }));
+    }
+
+    #[test]
+    fn reader_shows_the_lock_state() {
+        let mut fixture = Fixture::new(
+            DoorSecurityDoorType::LockFailSecure,
+            DoorSecurityInputs::none(),
+        );
+        assert_eq!(fixture.reader_leds(), vec![(ReaderLedColor::Red, 0)]);
+
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        });
+        let requests: Vec<_> = fixture.reader.try_iter().collect();
+        assert!(matches!(
+            requests[0].command,
+            MANAGECommand::ReaderLed {
+                color: ReaderLedColor::Green,
+                duration_ms: 5000,
+                ..
+            }
+        ));
+        assert!(matches!(
+            requests[1].command,
+            MANAGECommand::ReaderBuzzer { count: 1, .. }
+        ));
+
+        // Back to idle once, when the lock engages again
+        fixture.advance(Duration::from_secs(4));
+        assert!(fixture.reader_leds().is_empty());
+        fixture.advance(Duration::from_secs(2));
+        fixture.advance(Duration::from_secs(1));
+        assert_eq!(fixture.reader_leds(), vec![(ReaderLedColor::Red, 0)]);
+    }
+
+    #[test]
+    fn reader_shows_motorized_door_released_while_open() {
+        let closed_limit = FakeInputPin::default();
+        closed_limit.set(true);
+        let mut fixture = Fixture::new(
+            motorized(),
+            DoorSecurityInputs {
+                closed_limit: Some(closed_limit.clone()),
+                ..DoorSecurityInputs::none()
+            },
+        );
+        assert_eq!(fixture.reader_leds(), vec![(ReaderLedColor::Red, 0)]);
+
+        fixture.door.handle_command(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 3,
+        });
+        closed_limit.set(false);
+        fixture.advance(Duration::from_secs(11));
+        assert_eq!(fixture.reader_leds(), vec![(ReaderLedColor::Green, 0)]);
+
+        // Closing again after the unlock duration secures the reader
+        fixture.advance(Duration::from_secs(4));
+        assert_eq!(fixture.reader_leds(), vec![(ReaderLedColor::Red, 0)]);
+    }
+}
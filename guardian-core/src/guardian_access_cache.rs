@@ -0,0 +1,165 @@
+use std::collections::BTreeSet;
+
+use hex::encode;
+use libosdp::OsdpEventCardRead;
+use serde::{Deserialize, Serialize};
+
+use super::guardian_storage::GuardianStorage;
+use super::manage_command::{CommandOutcome, MANAGECommand};
+
+const ACCESS_CACHE_KEY: &str = "allowlist";
+const ACCESS_CACHE_MAX_CREDENTIALS: usize = 512;
+const ACCESS_CACHE_DEFAULT_UNLOCK_DURATION: u32 = 5;
+
+#[derive(Serialize, Deserialize)]
+struct AccessCacheContents {
+    credentials: BTreeSet<String>,
+    unlock_duration: u32,
+}
+
+impl Default for AccessCacheContents {
+    fn default() -> Self {
+        Self {
+            credentials: BTreeSet::new(),
+            unlock_duration: ACCESS_CACHE_DEFAULT_UNLOCK_DURATION,
+        }
+    }
+}
+
+// Local credential allowlist used while MANAGE is unreachable
+pub struct AccessCache {
+    contents: AccessCacheContents,
+    storage: GuardianStorage,
+}
+
+impl AccessCache {
+    pub fn load(storage: GuardianStorage) -> Self {
+        let contents: AccessCacheContents = storage.load(ACCESS_CACHE_KEY).unwrap_or_default();
+        log::info!(
+            "Access Cache Loaded ({} credentials)",
+            contents.credentials.len()
+        );
+        Self { contents, storage }
+    }
+
+    // Credentials are identified by the hex encoded raw card data
+    pub fn credential_id(event: &OsdpEventCardRead) -> String {
+        encode(&event.data)
+    }
+
+    // Returns the unlock duration if the card is on the allowlist
+    pub fn check(&self, event: &OsdpEventCardRead) -> Option<u32> {
+        self.check_credential(&Self::credential_id(event))
+    }
+
+    pub fn check_credential(&self, credential: &str) -> Option<u32> {
+        if self.contents.credentials.contains(credential) {
+            Some(self.contents.unlock_duration)
+        } else {
+            None
+        }
+    }
+
+    pub fn handle_command(&mut self, command: MANAGECommand) -> CommandOutcome {
+        match command {
+            MANAGECommand::AccessCacheSet {
+                credentials,
+                unlock_duration,
+            } => {
+                if credentials.len() > ACCESS_CACHE_MAX_CREDENTIALS {
+                    log::error!(
+                        "Access Cache rejected {} credentials (max {})",
+                        credentials.len(),
+                        ACCESS_CACHE_MAX_CREDENTIALS
+                    );
+                    return CommandOutcome::Rejected {
+                        reason: format!(
+                            "At most {} credentials can be cached",
+                            ACCESS_CACHE_MAX_CREDENTIALS
+                        ),
+                    };
+                }
+                self.contents.credentials = credentials.into_iter().collect();
+                self.contents.unlock_duration = unlock_duration;
+            }
+            MANAGECommand::AccessCacheAdd { credential } => {
+                if self.contents.credentials.len() >= ACCESS_CACHE_MAX_CREDENTIALS {
+                    log::error!("Access Cache full, credential not added!");
+                    return CommandOutcome::Rejected {
+                        reason: "Access cache full".to_string(),
+                    };
+                }
+                self.contents.credentials.insert(credential);
+            }
+            MANAGECommand::AccessCacheRemove { credential } => {
+                self.contents.credentials.remove(&credential);
+            }
+            MANAGECommand::AccessCacheClear => {
+                self.contents.credentials.clear();
+            }
+            _ => {
+                log::warn!("Access Cache ignoring command: {:?}", command);
+                return CommandOutcome::Rejected {
+                    reason: "Not an access cache command".to_string(),
+                };
+            }
+        }
+
+        // Persist the updated allowlist
+        log::info!(
+            "Access Cache Updated ({} credentials)",
+            self.contents.credentials.len()
+        );
+        if !self.storage.store(ACCESS_CACHE_KEY, &self.contents) {
+            return CommandOutcome::HardwareError {
+                error: "Failed to persist access cache".to_string(),
+            };
+        }
+        CommandOutcome::Success
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guardian_storage::MemoryStore;
+
+    fn access_cache(store: &MemoryStore) -> AccessCache {
+        AccessCache::load(GuardianStorage::new(store.clone()))
+    }
+
+    #[test]
+    fn allowlist_is_persisted() {
+        let store = MemoryStore::default();
+        let mut cache = access_cache(&store);
+        assert_eq!(cache.check_credential("0badc0de"), None);
+
+        cache.handle_command(MANAGECommand::AccessCacheSet {
+            credentials: vec!["0badc0de".to_string(), "cafe".to_string()],
+            unlock_duration: 7,
+        });
+        cache.handle_command(MANAGECommand::AccessCacheRemove {
+            credential: "cafe".to_string(),
+        });
+
+        let rebooted = access_cache(&store);
+        assert_eq!(rebooted.check_credential("0badc0de"), Some(7));
+        assert_eq!(rebooted.check_credential("cafe"), None);
+    }
+
+    #[test]
+    fn oversized_allowlist_is_rejected() {
+        let store = MemoryStore::default();
+        let mut cache = access_cache(&store);
+        cache.handle_command(MANAGECommand::AccessCacheAdd {
+            credential: "cafe".to_string(),
+        });
+        cache.handle_command(MANAGECommand::AccessCacheSet {
+            credentials: (0..=ACCESS_CACHE_MAX_CREDENTIALS)
+                .map(|credential| format!("{:08x}", credential))
+                .collect(),
+            unlock_duration: 5,
+        });
+        assert_eq!(cache.check_credential("cafe"), Some(5));
+    }
+}
@@ -0,0 +1,157 @@
+use std::ops::Range;
+
+use libosdp::{OsdpCardFormats, OsdpEventCardRead};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardFormat {
+    // Standard 26 bit Wiegand
+    #[serde(rename = "h10301")]
+    H10301,
+    // 34 bit Wiegand with a 16 bit facility code
+    #[serde(rename = "h10306")]
+    H10306,
+    // 35 bit HID Corporate 1000, the facility code being the company id
+    #[serde(rename = "corporate_1000")]
+    Corporate1000,
+}
+
+// Facility code and card number of a raw Wiegand read
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedCard {
+    pub format: CardFormat,
+    pub facility_code: u32,
+    pub card_number: u32,
+}
+
+// Decode a raw Wiegand read, None for ASCII reads and bit lengths of other formats
+pub fn decode_card(event: &OsdpEventCardRead) -> Result<Option<DecodedCard>, String> {
+    if event.format == OsdpCardFormats::Ascii || event.data.len() * 8 < event.nr_bits {
+        return Ok(None);
+    }
+
+    // Wiegand bits are sent first bit first, packed from the top of the first byte
+    let bits: Vec<bool> = (0..event.nr_bits)
+        .map(|bit| event.data[bit / 8] & (0x80 >> (bit % 8)) != 0)
+        .collect();
+    let ones = |covered: &dyn Fn(usize) -> bool| {
+        (0..bits.len())
+            .filter(|&bit| bits[bit] && covered(bit))
+            .count()
+    };
+    let even = |range: Range<usize>| ones(&|bit| range.contains(&bit)) % 2 == 0;
+    let odd = |range: Range<usize>| ones(&|bit| range.contains(&bit)) % 2 == 1;
+    let field = |range: Range<usize>| {
+        bits[range]
+            .iter()
+            .fold(0, |value, &bit| (value << 1) | bit as u32)
+    };
+
+    let (format, parity_ok, facility_code, card_number) = match bits.len() {
+        // Leading even parity over the first half, trailing odd parity over the second
+        26 => (
+            CardFormat::H10301,
+            even(0..13) && odd(13..26),
+            field(1..9),
+            field(9..25),
+        ),
+        34 => (
+            CardFormat::H10306,
+            even(0..17) && odd(17..34),
+            field(1..17),
+            field(17..33),
+        ),
+        35 => {
+            // Bit 1 is even parity over bits 2, 3, 5, 6, ... 33, bit 34 odd parity over
+            // bits 1, 2, 4, 5, ... 32 and bit 0 odd parity over the whole read
+            let even_ones = ones(&|bit| bit == 1 || ((2..34).contains(&bit) && bit % 3 != 1));
+            let odd_ones = ones(&|bit| bit > 0 && bit % 3 != 0);
+            let parity_ok = even_ones % 2 == 0 && odd_ones % 2 == 1 && odd(0..35);
+            (
+                CardFormat::Corporate1000,
+                parity_ok,
+                field(2..14),
+                field(14..34),
+            )
+        }
+        _ => return Ok(None),
+    };
+    if !parity_ok {
+        return Err(format!("{:?} parity error", format));
+    }
+    Ok(Some(DecodedCard {
+        format,
+        facility_code,
+        card_number,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card_read(bits: &str) -> OsdpEventCardRead {
+        let mut data = vec![0; bits.len().div_ceil(8)];
+        for (bit, value) in bits.chars().enumerate() {
+            if value == '1' {
+                data[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        OsdpEventCardRead {
+            format: OsdpCardFormats::Weigand,
+            nr_bits: bits.len(),
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn h10301_and_h10306_are_decoded() {
+        // Facility code 123, card number 4567
+        let event = card_read("10111101100010001110101110");
+        assert_eq!(
+            decode_card(&event),
+            Ok(Some(DecodedCard {
+                format: CardFormat::H10301,
+                facility_code: 123,
+                card_number: 4567,
+            }))
+        );
+
+        // Facility code 1000, card number 1
+        let event = card_read("0000000111110100000000000000000010");
+        assert_eq!(
+            decode_card(&event),
+            Ok(Some(DecodedCard {
+                format: CardFormat::H10306,
+                facility_code: 1000,
+                card_number: 1,
+            }))
+        );
+    }
+
+    #[test]
+    fn corporate_1000_is_decoded() {
+        // Company id 123, card number 456789
+        let event = card_read("10000001111011011011111000010101011");
+        assert_eq!(
+            decode_card(&event),
+            Ok(Some(DecodedCard {
+                format: CardFormat::Corporate1000,
+                facility_code: 123,
+                card_number: 456789,
+            }))
+        );
+    }
+
+    #[test]
+    fn bad_parity_is_rejected() {
+        let event = card_read("00111101100010001110101110");
+        assert!(decode_card(&event).is_err());
+        let event = card_read("10000001111011011011111000010101010");
+        assert!(decode_card(&event).is_err());
+
+        // Other lengths are left to MANAGE
+        assert_eq!(decode_card(&card_read("1010")), Ok(None));
+    }
+}
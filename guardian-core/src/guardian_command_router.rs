@@ -0,0 +1,334 @@
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use super::guardian_access_cache::AccessCache;
+use super::guardian_config::ConfigStore;
+use super::guardian_device_identity::ManageSession;
+use super::guardian_report_journal::ReportJournal;
+use super::manage_command::{CommandOutcome, CommandRequest, MANAGECommand, MANAGEReport};
+
+// Dispatches incoming MANAGE commands to the subsystem that owns them
+pub struct CommandRouter {
+    // Door thread command channels, indexed by door id
+    door_command_txs: Vec<Sender<CommandRequest>>,
+    reader_command_tx: Sender<CommandRequest>,
+    // None when the flash has no OTA slots to update
+    firmware_command_tx: Option<Sender<CommandRequest>>,
+    report_tx: Sender<MANAGEReport>,
+    access_cache: Arc<Mutex<AccessCache>>,
+    report_journal: Arc<Mutex<ReportJournal>>,
+    config_store: Arc<Mutex<ConfigStore>>,
+    session: Arc<Mutex<ManageSession>>,
+}
+
+impl CommandRouter {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        door_command_txs: Vec<Sender<CommandRequest>>,
+        reader_command_tx: Sender<CommandRequest>,
+        firmware_command_tx: Option<Sender<CommandRequest>>,
+        report_tx: Sender<MANAGEReport>,
+        access_cache: Arc<Mutex<AccessCache>>,
+        report_journal: Arc<Mutex<ReportJournal>>,
+        config_store: Arc<Mutex<ConfigStore>>,
+        session: Arc<Mutex<ManageSession>>,
+    ) -> Self {
+        Self {
+            door_command_txs,
+            reader_command_tx,
+            firmware_command_tx,
+            report_tx,
+            access_cache,
+            report_journal,
+            config_store,
+            session,
+        }
+    }
+
+    pub fn route(&self, command: MANAGECommand) {
+        // Only the handshake is unsigned, everything else must be signed for this session
+        if command.is_handshake() {
+            self.session.lock().unwrap().handle_command(command);
+            return;
+        }
+        let Some(request) = self
+            .session
+            .lock()
+            .unwrap()
+            .verify(command, SystemTime::now())
+        else {
+            return;
+        };
+
+        let request_id = request.request_id.clone();
+        let result = match request.command {
+            MANAGECommand::AccessCacheSet { .. }
+            | MANAGECommand::AccessCacheAdd { .. }
+            | MANAGECommand::AccessCacheRemove { .. }
+            | MANAGECommand::AccessCacheClear => self
+                .access_cache
+                .lock()
+                .unwrap()
+                .handle_command(request.command),
+            MANAGECommand::JournalAck { sequence } => {
+                self.report_journal.lock().unwrap().acknowledge(sequence);
+                CommandOutcome::Success
+            }
+            MANAGECommand::ConfigSet { .. } | MANAGECommand::ConfigGet => self
+                .config_store
+                .lock()
+                .unwrap()
+                .handle_command(request.command),
+            MANAGECommand::ReaderLed { .. }
+            | MANAGECommand::ReaderBuzzer { .. }
+            | MANAGECommand::ReaderText { .. } => {
+                // The OSDP thread reports the result once the reader has been sent the command
+                if self.reader_command_tx.send(request).is_ok() {
+                    return;
+                }
+                log::error!("OSDP control panel thread is not running!");
+                CommandOutcome::HardwareError {
+                    error: "OSDP control panel thread is not running".to_string(),
+                }
+            }
+            MANAGECommand::FirmwareUpdate { .. } => match &self.firmware_command_tx {
+                Some(firmware_command_tx) => {
+                    // The firmware thread reports the result once the image is installed
+                    if firmware_command_tx.send(request).is_ok() {
+                        return;
+                    }
+                    log::error!("Firmware update thread is not running!");
+                    CommandOutcome::HardwareError {
+                        error: "Firmware update thread is not running".to_string(),
+                    }
+                }
+                None => CommandOutcome::Rejected {
+                    reason: "Firmware updates are disabled on this controller".to_string(),
+                },
+            },
+            MANAGECommand::DoorOpen { door_id }
+            | MANAGECommand::DoorClose { door_id }
+            | MANAGECommand::DoorStop { door_id }
+            | MANAGECommand::DoorUnlock { door_id, .. } => {
+                match self.door_command_txs.get(door_id as usize) {
+                    Some(door_command_tx) => {
+                        // The door thread reports the result once the command has been carried out
+                        if door_command_tx.send(request).is_ok() {
+                            return;
+                        }
+                        log::error!("Door security thread {} is not running!", door_id);
+                        CommandOutcome::HardwareError {
+                            error: "Door security thread is not running".to_string(),
+                        }
+                    }
+                    None => CommandOutcome::Rejected {
+                        reason: format!("Unknown door {}", door_id),
+                    },
+                }
+            }
+            _ => {
+                log::warn!("Ignoring unexpected command: {:?}", request.command);
+                CommandOutcome::Rejected {
+                    reason: "Unexpected command".to_string(),
+                }
+            }
+        };
+
+        if let Some(request_id) = request_id {
+            self.report_tx
+                .send(MANAGEReport::CommandResult { request_id, result })
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+    use crate::guardian_device_identity::{command_signature, manage_signature, DeviceIdentity};
+    use crate::guardian_hal::fake::ManualClock;
+    use crate::guardian_storage::{GuardianStorage, MemoryStore};
+    use crate::manage_command::{CommandPayload, MANAGEReport};
+
+    const SECRET: [u8; 32] = [7; 32];
+
+    fn router(
+        authenticated: &'static AtomicBool,
+    ) -> (
+        CommandRouter,
+        Receiver<CommandRequest>,
+        Receiver<MANAGEReport>,
+    ) {
+        let storage = || GuardianStorage::new(MemoryStore::default());
+        let (door_command_tx, door_command_rx) = channel();
+        let (reader_command_tx, _) = channel();
+        let (report_tx, report_rx) = channel();
+        let identity = DeviceIdentity::new("02005e000001".to_string(), SECRET.to_vec());
+        let router = CommandRouter::new(
+            vec![door_command_tx],
+            reader_command_tx,
+            None,
+            report_tx.clone(),
+            Arc::new(Mutex::new(AccessCache::load(storage()))),
+            Arc::new(Mutex::new(ReportJournal::load(
+                storage(),
+                Arc::new(ManualClock::new()),
+            ))),
+            Arc::new(Mutex::new(ConfigStore::load(storage(), report_tx.clone()))),
+            Arc::new(Mutex::new(ManageSession::new(
+                identity,
+                report_tx,
+                authenticated,
+            ))),
+        );
+        (router, door_command_rx, report_rx)
+    }
+
+    fn authenticate(router: &CommandRouter, report_rx: &Receiver<MANAGEReport>) -> String {
+        router.route(MANAGECommand::AuthChallenge {
+            nonce: "aa55".to_string(),
+        });
+        let Ok(MANAGEReport::AuthResponse { nonce, .. }) = report_rx.try_recv() else {
+            panic!("no auth response");
+        };
+        router.route(MANAGECommand::AuthAccepted {
+            signature: manage_signature(&SECRET, "02005e000001", "aa55", &nonce),
+        });
+        nonce
+    }
+
+    fn signed(device_nonce: &str, counter: u64, command: MANAGECommand) -> MANAGECommand {
+        let payload = serde_json::to_string(&CommandPayload {
+            request_id: format!("r{}", counter),
+            counter,
+            timestamp: SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            command,
+        })
+        .unwrap();
+        let signature = command_signature(&SECRET, "02005e000001", "aa55", device_nonce, &payload);
+        MANAGECommand::Signed { payload, signature }
+    }
+
+    #[test]
+    fn only_signed_commands_reach_the_door() {
+        static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+        let (router, door_command_rx, report_rx) = router(&AUTHENTICATED);
+
+        router.route(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        });
+        assert!(door_command_rx.try_recv().is_err());
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::CommandRejected { .. })
+        ));
+
+        // Authenticated, but still unsigned
+        let nonce = authenticate(&router, &report_rx);
+        router.route(MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        });
+        assert!(door_command_rx.try_recv().is_err());
+
+        router.route(signed(
+            &nonce,
+            1,
+            MANAGECommand::DoorUnlock {
+                door_id: 0,
+                duration: 5,
+            },
+        ));
+        assert!(matches!(
+            door_command_rx.try_recv(),
+            Ok(CommandRequest {
+                request_id: Some(request_id),
+                command: MANAGECommand::DoorUnlock { duration: 5, .. },
+            }) if request_id == "r1"
+        ));
+    }
+
+    #[test]
+    fn results_are_reported_under_the_request_id() {
+        static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+        let (router, door_command_rx, report_rx) = router(&AUTHENTICATED);
+        let nonce = authenticate(&router, &report_rx);
+
+        router.route(signed(
+            &nonce,
+            1,
+            MANAGECommand::AccessCacheAdd {
+                credential: "cafe".to_string(),
+            },
+        ));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::CommandResult {
+                request_id,
+                result: CommandOutcome::Success,
+            }) if request_id == "r1"
+        ));
+
+        router.route(signed(
+            &nonce,
+            2,
+            MANAGECommand::ConfigSet {
+                config: serde_json::json!({"osdp_baud_rate": 1234}),
+            },
+        ));
+        let results: Vec<_> = report_rx.try_iter().collect();
+        assert!(results.iter().any(|report| matches!(
+            report,
+            MANAGEReport::CommandResult {
+                request_id,
+                result: CommandOutcome::Rejected { .. },
+            } if request_id == "r2"
+        )));
+
+        router.route(signed(&nonce, 3, MANAGECommand::DoorOpen { door_id: 1 }));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::CommandResult {
+                request_id,
+                result: CommandOutcome::Rejected { .. },
+            }) if request_id == "r3"
+        ));
+
+        // A door thread that died can't carry out the command
+        drop(door_command_rx);
+        router.route(signed(&nonce, 4, MANAGECommand::DoorOpen { door_id: 0 }));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::CommandResult {
+                request_id,
+                result: CommandOutcome::HardwareError { .. },
+            }) if request_id == "r4"
+        ));
+
+        // A controller without OTA slots can't be updated
+        router.route(signed(
+            &nonce,
+            5,
+            MANAGECommand::FirmwareUpdate {
+                url: "https://manage.example/guardian.bin".to_string(),
+                sha256: "00".repeat(32),
+                signature: "00".to_string(),
+            },
+        ));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::CommandResult {
+                request_id,
+                result: CommandOutcome::Rejected { .. },
+            }) if request_id == "r5"
+        ));
+    }
+}
@@ -0,0 +1,713 @@
+use std::collections::BTreeSet;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::aperture_door_security::{DoorInputConfig, DoorSecurityDoorType, MotorizedDoorConfig};
+use super::guardian_global_status::MAX_READERS;
+use super::guardian_reader_feedback::ReaderFeedbackPolicy;
+use super::guardian_storage::GuardianStorage;
+use super::manage_command::{CommandOutcome, MANAGECommand, MANAGEReport};
+
+const CONFIG_KEY: &str = "config";
+
+// Bump when a field changes meaning, and add a step to migrate()
+pub const CONFIG_VERSION: u32 = 2;
+
+// Fields that moved from the top level into doors[0] in version 2
+const V1_DOOR_FIELDS: [&str; 12] = [
+    "door_type",
+    "motor_pulse_ms",
+    "motor_travel_timeout_secs",
+    "limit_switches_fitted",
+    "limit_switch_active_high",
+    "door_position_sensor_fitted",
+    "door_position_active_high",
+    "request_to_exit_fitted",
+    "request_to_exit_active_high",
+    "input_debounce_ms",
+    "held_open_timeout_secs",
+    "request_to_exit_unlock_duration",
+];
+const V1_DOOR_PINS: [&str; 7] = [
+    "stop_unlock",
+    "open",
+    "close",
+    "open_limit",
+    "closed_limit",
+    "door_position",
+    "request_to_exit",
+];
+
+// Most doors a controller drives
+pub const MAX_DOORS: usize = 4;
+
+const OSDP_BAUD_RATES: [u32; 6] = [9_600, 19_200, 38_400, 57_600, 115_200, 230_400];
+
+// GPIOs used by the ESP32-POE for Ethernet, flash and the console UART
+const RESERVED_PINS: [i32; 19] = [
+    0, 1, 3, 5, 6, 7, 8, 9, 10, 11, 17, 18, 19, 21, 22, 23, 25, 26, 27,
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DoorKind {
+    // Fail-secure lock driven by the stop/unlock relay
+    LockFailSecure,
+    // Sliding or barrier door driven through open/close/stop inputs
+    Motorized,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReaderRole {
+    // Outside the door, reads cards of people coming in
+    Entry,
+    // Inside the door, reads cards of people leaving
+    Exit,
+}
+
+// One OSDP reader on the shared RS-485 bus
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ReaderConfig {
+    pub address: i32,
+    pub name: String,
+    pub role: ReaderRole,
+    pub door_id: u32,
+    // Cards are only sent to MANAGE together with a PIN typed after them
+    #[serde(default)]
+    pub card_and_pin: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuardianPins {
+    pub osdp_uart_tx: i32,
+    pub osdp_uart_rx: i32,
+    pub osdp_max485_rede: i32,
+}
+
+impl Default for GuardianPins {
+    fn default() -> Self {
+        Self {
+            osdp_uart_tx: 33,
+            osdp_uart_rx: 34,
+            osdp_max485_rede: 14,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DoorPins {
+    pub stop_unlock: i32,
+    pub open: i32,
+    pub close: i32,
+    pub open_limit: i32,
+    pub closed_limit: i32,
+    pub door_position: i32,
+    pub request_to_exit: i32,
+}
+
+impl Default for DoorPins {
+    fn default() -> Self {
+        Self {
+            stop_unlock: 13,
+            open: 32,
+            close: 4,
+            open_limit: 35,
+            closed_limit: 36,
+            door_position: 39,
+            request_to_exit: 15,
+        }
+    }
+}
+
+// One door with its own outputs, inputs and timers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DoorConfig {
+    pub door_type: DoorKind,
+    pub motor_pulse_ms: u64,
+    pub motor_travel_timeout_secs: u64,
+    pub limit_switches_fitted: bool,
+    pub limit_switch_active_high: bool,
+    pub door_position_sensor_fitted: bool,
+    pub door_position_active_high: bool,
+    pub request_to_exit_fitted: bool,
+    pub request_to_exit_active_high: bool,
+    pub input_debounce_ms: u64,
+    pub held_open_timeout_secs: u64,
+    pub request_to_exit_unlock_duration: u32,
+    pub pins: DoorPins,
+}
+
+impl Default for DoorConfig {
+    fn default() -> Self {
+        Self {
+            door_type: DoorKind::LockFailSecure,
+            motor_pulse_ms: 500,
+            motor_travel_timeout_secs: 15,
+            limit_switches_fitted: false,
+            limit_switch_active_high: false,
+            door_position_sensor_fitted: false,
+            door_position_active_high: true,
+            request_to_exit_fitted: false,
+            request_to_exit_active_high: false,
+            input_debounce_ms: 200,
+            held_open_timeout_secs: 30,
+            request_to_exit_unlock_duration: 5,
+            pins: DoorPins::default(),
+        }
+    }
+}
+
+impl DoorConfig {
+    fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("motor_pulse_ms", self.motor_pulse_ms),
+            ("motor_travel_timeout_secs", self.motor_travel_timeout_secs),
+            ("held_open_timeout_secs", self.held_open_timeout_secs),
+            (
+                "request_to_exit_unlock_duration",
+                self.request_to_exit_unlock_duration as u64,
+            ),
+        ] {
+            if value == 0 {
+                return Err(format!("{} must be greater than 0", name));
+            }
+        }
+        Ok(())
+    }
+
+    // Pins the door uses, with whether they are driven as outputs
+    fn used_pins(&self) -> Vec<(&'static str, i32, bool)> {
+        let pins = &self.pins;
+        let mut used = vec![
+            ("stop_unlock", pins.stop_unlock, true),
+            ("open", pins.open, true),
+            ("close", pins.close, true),
+        ];
+        if self.limit_switches_fitted {
+            used.push(("open_limit", pins.open_limit, false));
+            used.push(("closed_limit", pins.closed_limit, false));
+        }
+        if self.door_position_sensor_fitted {
+            used.push(("door_position", pins.door_position, false));
+        }
+        if self.request_to_exit_fitted {
+            used.push(("request_to_exit", pins.request_to_exit, false));
+        }
+        used
+    }
+
+    pub fn door_type(&self) -> DoorSecurityDoorType {
+        match self.door_type {
+            DoorKind::LockFailSecure => DoorSecurityDoorType::LockFailSecure,
+            DoorKind::Motorized => DoorSecurityDoorType::Motorized(MotorizedDoorConfig {
+                pulse_duration: Duration::from_millis(self.motor_pulse_ms),
+                travel_timeout: Duration::from_secs(self.motor_travel_timeout_secs),
+                limit_switch_active_high: self.limit_switch_active_high,
+            }),
+        }
+    }
+
+    pub fn door_input_config(&self) -> DoorInputConfig {
+        DoorInputConfig {
+            debounce: Duration::from_millis(self.input_debounce_ms),
+            door_position_active_high: self.door_position_active_high,
+            request_to_exit_active_high: self.request_to_exit_active_high,
+            held_open_timeout: Duration::from_secs(self.held_open_timeout_secs),
+            request_to_exit_unlock_duration: self.request_to_exit_unlock_duration,
+        }
+    }
+}
+
+// Site specific settings, stored in NVS and applied at boot
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuardianConfig {
+    pub version: u32,
+
+    // MANAGE
+    pub ws_base_uri: String,
+    pub ws_timeout_secs: u64,
+    pub heartbeat_interval_secs: u64,
+
+    // Doors in the order of their door id
+    pub doors: Vec<DoorConfig>,
+
+    // OSDP
+    pub osdp_baud_rate: u32,
+    // Talk to readers over OSDP Secure Channel, installing a key on readers still on the default
+    pub osdp_secure_channel: bool,
+    // Readers in the order of their reader id
+    pub readers: Vec<ReaderConfig>,
+    pub reader_feedback: ReaderFeedbackPolicy,
+
+    // GPIO
+    pub pins: GuardianPins,
+}
+
+impl Default for GuardianConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            ws_base_uri: "wss://manage.netinformatik.com/ws/office-security/door-commands/"
+                .to_string(),
+            ws_timeout_secs: 10,
+            heartbeat_interval_secs: 60,
+            doors: vec![DoorConfig::default()],
+            osdp_baud_rate: 9_600,
+            osdp_secure_channel: true,
+            readers: vec![ReaderConfig {
+                address: 0,
+                name: "entry".to_string(),
+                role: ReaderRole::Entry,
+                door_id: 0,
+                card_and_pin: false,
+            }],
+            reader_feedback: ReaderFeedbackPolicy::default(),
+            pins: GuardianPins::default(),
+        }
+    }
+}
+
+impl GuardianConfig {
+    // Load the stored config, falling back to the defaults if it is missing or unusable
+    pub fn load(storage: &GuardianStorage) -> Self {
+        let Some(stored) = storage.load::<Value>(CONFIG_KEY) else {
+            log::info!("No stored config, using defaults");
+            return Self::default();
+        };
+        match Self::migrate(stored).and_then(|config| config.validate().map(|_| config)) {
+            Ok(config) => {
+                log::info!("Config Loaded (version {})", config.version);
+                config
+            }
+            Err(error) => {
+                log::error!("Stored config rejected, using defaults: {}", error);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn store(&self, storage: &mut GuardianStorage) -> bool {
+        storage.store(CONFIG_KEY, self)
+    }
+
+    // Bring a stored config up to the current schema version
+    fn migrate(mut stored: Value) -> Result<Self, String> {
+        let mut version = stored.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+        // Version 1 configured a single door at the top level
+        if version == 1 {
+            let mut door = serde_json::Map::new();
+            for field in V1_DOOR_FIELDS {
+                if let Some(value) = stored.as_object_mut().and_then(|s| s.remove(field)) {
+                    door.insert(field.to_string(), value);
+                }
+            }
+            let mut door_pins = serde_json::Map::new();
+            for pin in V1_DOOR_PINS {
+                if let Some(value) = stored
+                    .get_mut("pins")
+                    .and_then(Value::as_object_mut)
+                    .and_then(|pins| pins.remove(pin))
+                {
+                    door_pins.insert(pin.to_string(), value);
+                }
+            }
+            door.insert("pins".to_string(), Value::Object(door_pins));
+            stored["doors"] = Value::Array(vec![Value::Object(door)]);
+            stored["version"] = Value::from(2);
+            version = 2;
+        }
+
+        match version {
+            2 => serde_json::from_value(stored).map_err(|error| error.to_string()),
+            _ => Err(format!("unsupported config version {}", version)),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version != CONFIG_VERSION {
+            return Err(format!("version must be {}", CONFIG_VERSION));
+        }
+        if !(self.ws_base_uri.starts_with("ws://") || self.ws_base_uri.starts_with("wss://"))
+            || !self.ws_base_uri.ends_with('/')
+        {
+            return Err("ws_base_uri must be a ws:// or wss:// URI ending in /".to_string());
+        }
+        for (name, value) in [
+            ("ws_timeout_secs", self.ws_timeout_secs),
+            ("heartbeat_interval_secs", self.heartbeat_interval_secs),
+        ] {
+            if value == 0 {
+                return Err(format!("{} must be greater than 0", name));
+            }
+        }
+        if self.doors.is_empty() || self.doors.len() > MAX_DOORS {
+            return Err(format!("doors must list 1 to {} doors", MAX_DOORS));
+        }
+        for (door_id, door) in self.doors.iter().enumerate() {
+            door.validate()
+                .map_err(|error| format!("door {}: {}", door_id, error))?;
+        }
+        if !OSDP_BAUD_RATES.contains(&self.osdp_baud_rate) {
+            return Err(format!(
+                "osdp_baud_rate must be one of {:?}",
+                OSDP_BAUD_RATES
+            ));
+        }
+        self.validate_readers()?;
+        self.validate_pins()
+    }
+
+    fn validate_readers(&self) -> Result<(), String> {
+        if self.readers.is_empty() || self.readers.len() > MAX_READERS {
+            return Err(format!("readers must list 1 to {} readers", MAX_READERS));
+        }
+        let mut seen = BTreeSet::new();
+        for reader in &self.readers {
+            // 0x7F is the broadcast address
+            if !(0..=126).contains(&reader.address) {
+                return Err(format!(
+                    "reader {} needs an address from 0 to 126",
+                    reader.name
+                ));
+            }
+            if !seen.insert(reader.address) {
+                return Err(format!("OSDP address {} is used twice", reader.address));
+            }
+            if reader.door_id as usize >= self.doors.len() {
+                return Err(format!(
+                    "reader {} controls unknown door {}",
+                    reader.name, reader.door_id
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_pins(&self) -> Result<(), String> {
+        let pins = &self.pins;
+        let mut used = vec![
+            ("osdp_uart_tx".to_string(), pins.osdp_uart_tx, true),
+            ("osdp_uart_rx".to_string(), pins.osdp_uart_rx, false),
+            ("osdp_max485_rede".to_string(), pins.osdp_max485_rede, true),
+        ];
+        for (door_id, door) in self.doors.iter().enumerate() {
+            for (name, pin, output) in door.used_pins() {
+                used.push((format!("door {} {}", door_id, name), pin, output));
+            }
+        }
+
+        let mut seen = BTreeSet::new();
+        for (name, pin, output) in used {
+            let exists = matches!(pin, 0..=19 | 21..=23 | 25..=27 | 32..=39);
+            if !exists || RESERVED_PINS.contains(&pin) {
+                return Err(format!("{} can't use GPIO {}", name, pin));
+            }
+            // GPIO 34 and up are input only
+            if output && pin >= 34 {
+                return Err(format!(
+                    "{} needs an output capable GPIO, not {}",
+                    name, pin
+                ));
+            }
+            if !seen.insert(pin) {
+                return Err(format!("GPIO {} is assigned twice ({})", pin, name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn ws_timeout(&self) -> Duration {
+        Duration::from_secs(self.ws_timeout_secs)
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs)
+    }
+
+    // Reader ids of the readers at a door
+    pub fn door_readers(&self, door_id: u32) -> Vec<i32> {
+        (0..self.readers.len() as i32)
+            .filter(|&reader| self.readers[reader as usize].door_id == door_id)
+            .collect()
+    }
+}
+
+// Overlay the fields present in the update onto the current config, a list takes the
+// length of the update with each entry overlaid onto the entry at the same position
+fn merge(target: &mut Value, update: Value) {
+    match (target, update) {
+        (Value::Object(target), Value::Object(update)) => {
+            for (key, value) in update {
+                merge(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (Value::Array(target), Value::Array(update)) => {
+            target.truncate(update.len());
+            for (index, value) in update.into_iter().enumerate() {
+                match target.get_mut(index) {
+                    Some(entry) => merge(entry, value),
+                    None => target.push(value),
+                }
+            }
+        }
+        (target, update) => *target = update,
+    }
+}
+
+// Handles ConfigSet/ConfigGet, changes take effect at the next boot
+pub struct ConfigStore {
+    running: GuardianConfig,
+    stored: GuardianConfig,
+    storage: GuardianStorage,
+    report_tx: Sender<MANAGEReport>,
+}
+
+impl ConfigStore {
+    pub fn load(storage: GuardianStorage, report_tx: Sender<MANAGEReport>) -> Self {
+        let config = GuardianConfig::load(&storage);
+        Self {
+            running: config.clone(),
+            stored: config,
+            storage,
+            report_tx,
+        }
+    }
+
+    // The config this boot was started with
+    pub fn running(&self) -> &GuardianConfig {
+        &self.running
+    }
+
+    pub fn handle_command(&mut self, command: MANAGECommand) -> CommandOutcome {
+        match command {
+            MANAGECommand::ConfigSet { config } => match self.merge_update(config) {
+                Ok(config) => {
+                    if !config.store(&mut self.storage) {
+                        log::error!("Failed to write config!");
+                        return CommandOutcome::HardwareError {
+                            error: "Failed to write config".to_string(),
+                        };
+                    }
+                    self.stored = config;
+                    log::info!(
+                        "Config Updated, restart required: {}",
+                        self.restart_required()
+                    );
+                    self.report_config();
+                    CommandOutcome::Success
+                }
+                Err(error) => {
+                    log::error!("Config Rejected: {}", error);
+                    self.report_tx
+                        .send(MANAGEReport::ConfigRejected {
+                            error: error.clone(),
+                        })
+                        .unwrap();
+                    CommandOutcome::Rejected { reason: error }
+                }
+            },
+            MANAGECommand::ConfigGet => {
+                self.report_config();
+                CommandOutcome::Success
+            }
+            _ => {
+                log::warn!("Config store received non-config command: {:?}", command);
+                CommandOutcome::Rejected {
+                    reason: "Not a config command".to_string(),
+                }
+            }
+        }
+    }
+
+    // The stored config with the update applied, if the result is valid
+    fn merge_update(&self, update: Value) -> Result<GuardianConfig, String> {
+        let mut merged = serde_json::to_value(&self.stored).unwrap();
+        merge(&mut merged, update);
+        let config: GuardianConfig =
+            serde_json::from_value(merged).map_err(|error| error.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn restart_required(&self) -> bool {
+        self.stored != self.running
+    }
+
+    fn report_config(&self) {
+        let report = MANAGEReport::Config {
+            config: self.stored.clone(),
+            restart_required: self.restart_required(),
+        };
+        self.report_tx.send(report).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::guardian_storage::MemoryStore;
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(GuardianConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_pins_are_rejected() {
+        let mut config = GuardianConfig::default();
+        config.doors[0].pins.stop_unlock = 36;
+        assert!(config.validate().is_err());
+
+        let mut config = GuardianConfig::default();
+        config.doors[0].pins.open = 23;
+        assert!(config.validate().is_err());
+
+        // Unfitted inputs don't claim their pin
+        let mut config = GuardianConfig::default();
+        config.doors[0].pins.door_position = config.doors[0].pins.stop_unlock;
+        assert_eq!(config.validate(), Ok(()));
+        config.doors[0].door_position_sensor_fitted = true;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn doors_need_their_own_pins() {
+        let mut config = GuardianConfig::default();
+        config.doors.push(DoorConfig::default());
+        assert!(config.validate().is_err());
+
+        config.doors[1].pins = DoorPins {
+            stop_unlock: 16,
+            open: 2,
+            close: 12,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+
+        // Readers can only be at a configured door
+        config.readers[0].door_id = 2;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn version_1_door_moves_into_doors() {
+        let store = MemoryStore::default();
+        let mut storage = GuardianStorage::new(store.clone());
+        storage.store(
+            CONFIG_KEY,
+            &json!({
+                "version": 1,
+                "door_type": "motorized",
+                "limit_switches_fitted": true,
+                "osdp_baud_rate": 115_200,
+                "pins": {"osdp_uart_tx": 33, "open": 16}
+            }),
+        );
+
+        let config = GuardianConfig::load(&GuardianStorage::new(store));
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.osdp_baud_rate, 115_200);
+        assert_eq!(config.doors.len(), 1);
+        assert_eq!(config.doors[0].door_type, DoorKind::Motorized);
+        assert!(config.doors[0].limit_switches_fitted);
+        assert_eq!(config.doors[0].pins.open, 16);
+        assert_eq!(config.doors[0].pins.close, 4);
+    }
+
+    #[test]
+    fn invalid_readers_are_rejected() {
+        let mut config = GuardianConfig::default();
+        config.readers.push(ReaderConfig {
+            address: 1,
+            name: "exit".to_string(),
+            role: ReaderRole::Exit,
+            door_id: 0,
+            card_and_pin: false,
+        });
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.door_readers(0), [0, 1]);
+
+        config.readers[1].address = 0;
+        assert!(config.validate().is_err());
+        config.readers[1].address = 127;
+        assert!(config.validate().is_err());
+
+        config.readers.clear();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn unknown_versions_fall_back_to_defaults() {
+        let store = MemoryStore::default();
+        let mut storage = GuardianStorage::new(store.clone());
+        let mut stored = serde_json::to_value(GuardianConfig::default()).unwrap();
+        stored["version"] = json!(CONFIG_VERSION + 1);
+        stored["osdp_baud_rate"] = json!(115_200);
+        storage.store(CONFIG_KEY, &stored);
+
+        let config = GuardianConfig::load(&GuardianStorage::new(store));
+        assert_eq!(config, GuardianConfig::default());
+    }
+
+    #[test]
+    fn config_set_merges_persists_and_needs_restart() {
+        let store = MemoryStore::default();
+        let (report_tx, report_rx) = channel();
+        let mut config_store = ConfigStore::load(GuardianStorage::new(store.clone()), report_tx);
+
+        config_store.handle_command(MANAGECommand::ConfigSet {
+            config: json!({"doors": [{"door_type": "motorized", "pins": {"open": 16}}]}),
+        });
+        match report_rx.try_recv() {
+            Ok(MANAGEReport::Config {
+                config,
+                restart_required,
+            }) => {
+                assert_eq!(config.doors[0].door_type, DoorKind::Motorized);
+                assert_eq!(config.doors[0].pins.open, 16);
+                assert_eq!(config.doors[0].pins.close, 4);
+                assert!(restart_required);
+            }
+            report => panic!("unexpected report {:?}", report),
+        }
+        assert_eq!(
+            config_store.running().doors[0].door_type,
+            DoorKind::LockFailSecure
+        );
+
+        // Rejected updates leave the stored config untouched
+        config_store.handle_command(MANAGECommand::ConfigSet {
+            config: json!({"osdp_baud_rate": 1234}),
+        });
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::ConfigRejected { .. })
+        ));
+        config_store.handle_command(MANAGECommand::ConfigSet {
+            config: json!({"not_a_setting": true}),
+        });
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::ConfigRejected { .. })
+        ));
+
+        // The update is applied at the next boot
+        let config = GuardianConfig::load(&GuardianStorage::new(store));
+        assert_eq!(config.doors[0].door_type, DoorKind::Motorized);
+        assert_eq!(config.osdp_baud_rate, 9_600);
+    }
+}
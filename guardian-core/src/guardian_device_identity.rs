@@ -0,0 +1,506 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hex::encode;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::guardian_storage::GuardianStorage;
+use super::manage_command::{CommandPayload, CommandRequest, MANAGECommand, MANAGEReport};
+
+// Raw 32 byte secret, provisioned into the "identity" NVS namespace at the factory
+const DEVICE_SECRET_KEY: &str = "device_secret";
+const DEVICE_SECRET_LEN: usize = 32;
+const NONCE_LEN: usize = 16;
+
+// Signed commands older or newer than this are rejected, once the wall clock is set
+const MAX_COMMAND_AGE_SECS: u64 = 30;
+// Before SNTP has synced the wall clock still reads 1970, 2024-01-01 is safely past that
+const WALL_CLOCK_VALID_AFTER: u64 = 1_704_067_200;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Who this controller is and the secret it shares with MANAGE
+#[derive(Clone)]
+pub struct DeviceIdentity {
+    pub mac_address: String,
+    secret: Option<Vec<u8>>,
+}
+
+impl DeviceIdentity {
+    pub fn new(mac_address: String, secret: Vec<u8>) -> Self {
+        Self {
+            mac_address,
+            secret: Some(secret),
+        }
+    }
+
+    pub fn load(storage: &GuardianStorage, mac_address: String) -> Self {
+        let secret = storage
+            .load_bytes(DEVICE_SECRET_KEY)
+            .filter(|secret| secret.len() == DEVICE_SECRET_LEN);
+        if secret.is_none() {
+            log::error!("No device secret provisioned, MANAGE will not accept this controller!");
+        }
+        Self {
+            mac_address,
+            secret,
+        }
+    }
+
+    // The firmware image with this sha256 was signed by MANAGE for this controller
+    pub fn verify_firmware(&self, sha256: &str, signature: &str) -> bool {
+        let Some(secret) = &self.secret else {
+            return false;
+        };
+        let parts = ["guardian-firmware", self.mac_address.as_str(), sha256];
+        verify_signature(secret, &parts, signature)
+    }
+}
+
+fn sign(secret: &[u8], parts: &[&str]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+    mac.update(parts.join(":").as_bytes());
+    mac
+}
+
+// Proof sent by the controller that it holds the device secret
+pub fn device_signature(
+    secret: &[u8],
+    mac_address: &str,
+    manage_nonce: &str,
+    device_nonce: &str,
+) -> String {
+    let parts = ["guardian-device", mac_address, manage_nonce, device_nonce];
+    encode(sign(secret, &parts).finalize().into_bytes())
+}
+
+// Proof sent back by MANAGE that it holds the same secret
+pub fn manage_signature(
+    secret: &[u8],
+    mac_address: &str,
+    manage_nonce: &str,
+    device_nonce: &str,
+) -> String {
+    let parts = ["guardian-manage", mac_address, manage_nonce, device_nonce];
+    encode(sign(secret, &parts).finalize().into_bytes())
+}
+
+// Signature MANAGE puts on every command sent during an authenticated session
+pub fn command_signature(
+    secret: &[u8],
+    mac_address: &str,
+    manage_nonce: &str,
+    device_nonce: &str,
+    payload: &str,
+) -> String {
+    let parts = [
+        "guardian-command",
+        mac_address,
+        manage_nonce,
+        device_nonce,
+        payload,
+    ];
+    encode(sign(secret, &parts).finalize().into_bytes())
+}
+
+// Signature MANAGE puts on a firmware image for this controller
+pub fn firmware_signature(secret: &[u8], mac_address: &str, sha256: &str) -> String {
+    let parts = ["guardian-firmware", mac_address, sha256];
+    encode(sign(secret, &parts).finalize().into_bytes())
+}
+
+fn verify_signature(secret: &[u8], parts: &[&str], signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => sign(secret, parts).verify_slice(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+// Why a command was dropped, with its request_id once the signed payload could be read
+#[derive(Debug, PartialEq)]
+struct Rejection {
+    request_id: Option<String>,
+    reason: String,
+}
+
+impl Rejection {
+    fn new(reason: &str) -> Self {
+        Self {
+            request_id: None,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+// Challenge-response handshake run at the start of every MANAGE connection
+//
+// MANAGE -> auth.challenge {nonce}
+// Guardian -> auth.response {mac_address, nonce, signature}
+// MANAGE -> auth.accepted {signature}
+//
+// Every command after that is signed over both nonces, so frames can't be replayed into
+// another session, and carries an increasing counter so they can't be replayed within it
+pub struct ManageSession {
+    identity: DeviceIdentity,
+    report_tx: Sender<MANAGEReport>,
+    authenticated: &'static AtomicBool,
+    challenge: Option<(String, String)>,
+    session_nonces: Option<(String, String)>,
+    last_counter: u64,
+}
+
+impl ManageSession {
+    pub fn new(
+        identity: DeviceIdentity,
+        report_tx: Sender<MANAGEReport>,
+        authenticated: &'static AtomicBool,
+    ) -> Self {
+        Self {
+            identity,
+            report_tx,
+            authenticated,
+            challenge: None,
+            session_nonces: None,
+            last_counter: 0,
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::SeqCst)
+    }
+
+    pub fn handle_command(&mut self, command: MANAGECommand) {
+        match command {
+            MANAGECommand::AuthChallenge { nonce } => {
+                // A new challenge starts a new session
+                self.authenticated.store(false, Ordering::SeqCst);
+                self.session_nonces = None;
+                self.last_counter = 0;
+                let Some(secret) = &self.identity.secret else {
+                    log::error!("Can't answer MANAGE challenge without a device secret!");
+                    return;
+                };
+
+                let mut device_nonce = [0u8; NONCE_LEN];
+                getrandom::getrandom(&mut device_nonce).unwrap();
+                let device_nonce = encode(device_nonce);
+                let signature =
+                    device_signature(secret, &self.identity.mac_address, &nonce, &device_nonce);
+                self.report_tx
+                    .send(MANAGEReport::AuthResponse {
+                        mac_address: self.identity.mac_address.clone(),
+                        nonce: device_nonce.clone(),
+                        signature,
+                    })
+                    .unwrap();
+                self.challenge = Some((nonce, device_nonce));
+            }
+            MANAGECommand::AuthAccepted { signature } => {
+                let (Some(secret), Some((manage_nonce, device_nonce))) =
+                    (&self.identity.secret, self.challenge.take())
+                else {
+                    log::error!("MANAGE accepted a session that was never challenged!");
+                    return;
+                };
+
+                let parts = [
+                    "guardian-manage",
+                    &self.identity.mac_address,
+                    &manage_nonce,
+                    &device_nonce,
+                ];
+                if verify_signature(secret, &parts, &signature) {
+                    log::info!("Authenticated with MANAGE!");
+                    self.session_nonces = Some((manage_nonce, device_nonce));
+                    self.authenticated.store(true, Ordering::SeqCst);
+                } else {
+                    log::error!("MANAGE failed to prove it holds the device secret!");
+                }
+            }
+            _ => {
+                log::warn!("Session received non-auth command: {:?}", command);
+            }
+        }
+    }
+
+    // Unwrap a signed command, reporting it to MANAGE if it can't be trusted
+    pub fn verify(&mut self, command: MANAGECommand, now: SystemTime) -> Option<CommandRequest> {
+        match self.open(command, now) {
+            Ok(payload) => Some(CommandRequest {
+                request_id: Some(payload.request_id),
+                command: payload.command,
+            }),
+            Err(Rejection { request_id, reason }) => {
+                log::warn!("Rejected command {:?}: {}", request_id, reason);
+                self.report_tx
+                    .send(MANAGEReport::CommandRejected { request_id, reason })
+                    .unwrap();
+                None
+            }
+        }
+    }
+
+    fn open(
+        &mut self,
+        command: MANAGECommand,
+        now: SystemTime,
+    ) -> Result<CommandPayload, Rejection> {
+        let MANAGECommand::Signed { payload, signature } = command else {
+            return Err(Rejection::new("Unsigned command"));
+        };
+        let (Some(secret), Some((manage_nonce, device_nonce)), true) = (
+            &self.identity.secret,
+            &self.session_nonces,
+            self.is_authenticated(),
+        ) else {
+            return Err(Rejection::new("Session not authenticated"));
+        };
+
+        // Check the signature before looking at anything in the payload
+        let parts = [
+            "guardian-command",
+            &self.identity.mac_address,
+            manage_nonce,
+            device_nonce,
+            &payload,
+        ];
+        if !verify_signature(secret, &parts, &signature) {
+            return Err(Rejection::new("Invalid signature"));
+        }
+        let payload: CommandPayload =
+            serde_json::from_str(&payload).map_err(|error| Rejection {
+                // A command this firmware doesn't know still names its request
+                request_id: serde_json::from_str::<serde_json::Value>(&payload)
+                    .ok()
+                    .and_then(|value| Some(value.get("request_id")?.as_str()?.to_string())),
+                reason: format!("Invalid payload: {}", error),
+            })?;
+
+        // From here on MANAGE can match the rejection to its request
+        let rejected = |reason: String| Rejection {
+            request_id: Some(payload.request_id.clone()),
+            reason,
+        };
+        if payload.command.is_handshake() || matches!(payload.command, MANAGECommand::Signed { .. })
+        {
+            return Err(rejected("Nested command".to_string()));
+        }
+        if payload.counter <= self.last_counter {
+            return Err(rejected(format!("Replayed counter {}", payload.counter)));
+        }
+
+        // The counter already stops replays, the timestamp also stops commands held back
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        if now >= WALL_CLOCK_VALID_AFTER && now.abs_diff(payload.timestamp) > MAX_COMMAND_AGE_SECS {
+            return Err(rejected(format!("Stale timestamp {}", payload.timestamp)));
+        }
+
+        self.last_counter = payload.counter;
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    const SECRET: [u8; DEVICE_SECRET_LEN] = [7; DEVICE_SECRET_LEN];
+    const MAC_ADDRESS: &str = "02005e000001";
+
+    fn challenge(
+        session: &mut ManageSession,
+        report_rx: &std::sync::mpsc::Receiver<MANAGEReport>,
+    ) -> String {
+        session.handle_command(MANAGECommand::AuthChallenge {
+            nonce: "aa55".to_string(),
+        });
+        match report_rx.try_recv() {
+            Ok(MANAGEReport::AuthResponse {
+                mac_address,
+                nonce,
+                signature,
+            }) => {
+                assert_eq!(mac_address, MAC_ADDRESS);
+                assert_eq!(
+                    signature,
+                    device_signature(&SECRET, MAC_ADDRESS, "aa55", &nonce)
+                );
+                nonce
+            }
+            report => panic!("unexpected report {:?}", report),
+        }
+    }
+
+    #[test]
+    fn handshake_authenticates_both_sides() {
+        static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+        let (report_tx, report_rx) = channel();
+        let identity = DeviceIdentity::new(MAC_ADDRESS.to_string(), SECRET.to_vec());
+        let mut session = ManageSession::new(identity, report_tx, &AUTHENTICATED);
+
+        let device_nonce = challenge(&mut session, &report_rx);
+        assert!(!session.is_authenticated());
+        session.handle_command(MANAGECommand::AuthAccepted {
+            signature: manage_signature(&SECRET, MAC_ADDRESS, "aa55", &device_nonce),
+        });
+        assert!(session.is_authenticated());
+
+        // The next challenge drops the session until it is accepted again
+        challenge(&mut session, &report_rx);
+        assert!(!session.is_authenticated());
+    }
+
+    #[test]
+    fn manage_with_the_wrong_secret_is_not_trusted() {
+        static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+        let (report_tx, report_rx) = channel();
+        let identity = DeviceIdentity::new(MAC_ADDRESS.to_string(), SECRET.to_vec());
+        let mut session = ManageSession::new(identity, report_tx, &AUTHENTICATED);
+
+        let device_nonce = challenge(&mut session, &report_rx);
+        session.handle_command(MANAGECommand::AuthAccepted {
+            signature: manage_signature(&[8; 32], MAC_ADDRESS, "aa55", &device_nonce),
+        });
+        assert!(!session.is_authenticated());
+
+        // Replaying a valid acceptance without a fresh challenge doesn't work either
+        session.handle_command(MANAGECommand::AuthAccepted {
+            signature: manage_signature(&SECRET, MAC_ADDRESS, "aa55", &device_nonce),
+        });
+        assert!(!session.is_authenticated());
+    }
+
+    fn authenticated_session(authenticated: &'static AtomicBool) -> (ManageSession, String) {
+        let (report_tx, report_rx) = channel();
+        let identity = DeviceIdentity::new(MAC_ADDRESS.to_string(), SECRET.to_vec());
+        let mut session = ManageSession::new(identity, report_tx, authenticated);
+        let device_nonce = challenge(&mut session, &report_rx);
+        session.handle_command(MANAGECommand::AuthAccepted {
+            signature: manage_signature(&SECRET, MAC_ADDRESS, "aa55", &device_nonce),
+        });
+        (session, device_nonce)
+    }
+
+    fn signed(device_nonce: &str, counter: u64, timestamp: u64) -> MANAGECommand {
+        let payload = serde_json::to_string(&CommandPayload {
+            request_id: format!("r{}", counter),
+            counter,
+            timestamp,
+            command: MANAGECommand::DoorUnlock {
+                door_id: 0,
+                duration: 5,
+            },
+        })
+        .unwrap();
+        let signature = command_signature(&SECRET, MAC_ADDRESS, "aa55", device_nonce, &payload);
+        MANAGECommand::Signed { payload, signature }
+    }
+
+    #[test]
+    fn signed_commands_are_accepted_once() {
+        static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+        let (mut session, device_nonce) = authenticated_session(&AUTHENTICATED);
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_750_000_000);
+
+        let command = signed(&device_nonce, 1, 1_750_000_000);
+        assert!(matches!(
+            session.open(command.clone(), now),
+            Ok(CommandPayload {
+                command: MANAGECommand::DoorUnlock {
+                    door_id: 0,
+                    duration: 5,
+                },
+                ..
+            })
+        ));
+        assert_eq!(
+            session.open(command, now).unwrap_err(),
+            Rejection {
+                request_id: Some("r1".to_string()),
+                reason: "Replayed counter 1".to_string(),
+            }
+        );
+        assert!(session
+            .open(signed(&device_nonce, 2, 1_750_000_010), now)
+            .is_ok());
+    }
+
+    #[test]
+    fn tampered_stale_and_unsigned_commands_are_rejected() {
+        static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+        let (mut session, device_nonce) = authenticated_session(&AUTHENTICATED);
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_750_000_000);
+
+        let MANAGECommand::Signed { payload, signature } = signed(&device_nonce, 1, 1_750_000_000)
+        else {
+            unreachable!();
+        };
+        let tampered = MANAGECommand::Signed {
+            payload: payload.replace("\"duration\":5", "\"duration\":500"),
+            signature,
+        };
+        assert_eq!(
+            session.open(tampered, now).unwrap_err(),
+            Rejection::new("Invalid signature")
+        );
+        assert_eq!(
+            session
+                .open(signed(&device_nonce, 1, 1_749_999_000), now)
+                .unwrap_err()
+                .reason,
+            "Stale timestamp 1749999000"
+        );
+        assert_eq!(
+            session
+                .open(
+                    MANAGECommand::DoorUnlock {
+                        door_id: 0,
+                        duration: 5,
+                    },
+                    now
+                )
+                .unwrap_err(),
+            Rejection::new("Unsigned command")
+        );
+
+        // A command this firmware doesn't know is still matched to its request
+        let payload = r#"{"request_id":"r9","counter":9,"timestamp":0,"command":"door.fly"}"#;
+        let signature = command_signature(&SECRET, MAC_ADDRESS, "aa55", &device_nonce, payload);
+        let unknown = MANAGECommand::Signed {
+            payload: payload.to_string(),
+            signature,
+        };
+        assert_eq!(
+            session.open(unknown, now).unwrap_err().request_id,
+            Some("r9".to_string())
+        );
+
+        // Without a synced wall clock only the counter protects against replays
+        assert!(session
+            .open(signed(&device_nonce, 1, 1_749_999_000), UNIX_EPOCH)
+            .is_ok());
+    }
+
+    #[test]
+    fn commands_from_another_session_are_rejected() {
+        static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+        let (mut session, device_nonce) = authenticated_session(&AUTHENTICATED);
+        let command = signed(&device_nonce, 1, 0);
+
+        // The next session has a fresh device nonce, so the old signature no longer holds
+        let (report_tx, report_rx) = channel();
+        session.report_tx = report_tx;
+        let device_nonce = challenge(&mut session, &report_rx);
+        session.handle_command(MANAGECommand::AuthAccepted {
+            signature: manage_signature(&SECRET, MAC_ADDRESS, "aa55", &device_nonce),
+        });
+        assert_eq!(
+            session.open(command, UNIX_EPOCH).unwrap_err(),
+            Rejection::new("Invalid signature")
+        );
+    }
+}
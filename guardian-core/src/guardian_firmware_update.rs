@@ -0,0 +1,302 @@
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use atomic_time::AtomicInstant;
+use sha2::{Digest, Sha256};
+
+use super::guardian_device_identity::DeviceIdentity;
+use super::guardian_hal::FirmwareSlots;
+use super::guardian_storage::GuardianStorage;
+use super::manage_command::{CommandOutcome, MANAGEReport};
+
+// sha256 of the image installed last, kept in the "firmware" NVS namespace until the
+// image passed its health check or was rolled back
+const PENDING_UPDATE_KEY: &str = "pending_sha256";
+
+// A new image that isn't healthy within this long after boot is rolled back
+pub const FIRMWARE_CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Hashes the image as it is written, so the slot is only booted if it is the one MANAGE signed
+pub struct ImageCheck<'a> {
+    hasher: Sha256,
+    expected: Vec<u8>,
+    // Set once the whole image went through
+    matched: Option<bool>,
+    // Ticked for every chunk so the watchdog sees a long download making progress
+    last_tick: &'a AtomicInstant,
+}
+
+impl ImageCheck<'_> {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.keep_alive();
+    }
+
+    // Called around every step of the install that blocks, each of which has to finish well
+    // within the watchdog's stall timeout
+    pub fn keep_alive(&self) {
+        self.last_tick.store(Instant::now(), Ordering::SeqCst);
+    }
+
+    // Called once the download is complete, the slot may only be booted if this is true
+    pub fn finish(&mut self) -> bool {
+        let matched = self.hasher.clone().finalize().as_slice() == self.expected;
+        self.matched = Some(matched);
+        matched
+    }
+}
+
+// Installs images sent by MANAGE and confirms or rolls back the running one
+pub struct FirmwareUpdater<F: FirmwareSlots> {
+    slots: F,
+    identity: DeviceIdentity,
+    storage: GuardianStorage,
+    report_tx: Sender<MANAGEReport>,
+    booted_at: Instant,
+    // Waiting for the first healthy check of a new image
+    confirming: bool,
+}
+
+impl<F: FirmwareSlots> FirmwareUpdater<F> {
+    pub fn new(
+        slots: F,
+        identity: DeviceIdentity,
+        storage: GuardianStorage,
+        report_tx: Sender<MANAGEReport>,
+        booted_at: Instant,
+    ) -> Self {
+        let mut updater = Self {
+            confirming: slots.is_pending_verify(),
+            slots,
+            identity,
+            storage,
+            report_tx,
+            booted_at,
+        };
+
+        // An update that is still pending but no longer running was rolled back
+        if !updater.confirming {
+            if let Some(sha256) = updater.pending_update() {
+                let version = updater.slots.running_version();
+                log::error!(
+                    "Firmware {} was rolled back, running {} again",
+                    sha256,
+                    version
+                );
+                updater.set_pending_update(None);
+                updater
+                    .report_tx
+                    .send(MANAGEReport::FirmwareRolledBack { sha256, version })
+                    .unwrap();
+            }
+        }
+        updater
+    }
+
+    // Download, verify and activate the image, the caller restarts into it on success
+    pub fn install(
+        &mut self,
+        url: &str,
+        sha256: &str,
+        signature: &str,
+        last_tick: &AtomicInstant,
+    ) -> CommandOutcome {
+        // Check the signature before spending time on the download
+        if !self.identity.verify_firmware(sha256, signature) {
+            log::warn!("Rejected firmware {} with a bad signature", sha256);
+            return CommandOutcome::Rejected {
+                reason: "Invalid firmware signature".to_string(),
+            };
+        }
+        let expected = match hex::decode(sha256) {
+            Ok(expected) if expected.len() == 32 => expected,
+            _ => {
+                return CommandOutcome::Rejected {
+                    reason: "sha256 must be 64 hex digits".to_string(),
+                }
+            }
+        };
+
+        log::info!("Installing firmware {} from {}", sha256, url);
+        let mut image_check = ImageCheck {
+            hasher: Sha256::new(),
+            expected,
+            matched: None,
+            last_tick,
+        };
+        if let Err(error) = self.slots.install(url, &mut image_check) {
+            log::error!("Failed to install firmware {}: {:?}", sha256, error);
+            if image_check.matched == Some(false) {
+                return CommandOutcome::Rejected {
+                    reason: "Firmware image does not match its sha256".to_string(),
+                };
+            }
+            return CommandOutcome::HardwareError {
+                error: format!("Failed to install firmware: {:?}", error),
+            };
+        }
+
+        self.set_pending_update(Some(sha256.to_string()));
+        CommandOutcome::Success
+    }
+
+    pub fn restart(&mut self) {
+        self.slots.restart();
+    }
+
+    // Called once a second, keeps a new image once it is healthy
+    pub fn check_health(&mut self, healthy: bool, now: Instant) {
+        if !self.confirming {
+            return;
+        }
+
+        let sha256 = self.pending_update().unwrap_or_default();
+        if healthy {
+            if let Err(error) = self.slots.mark_valid() {
+                log::error!("Failed to mark firmware {} valid: {:?}", sha256, error);
+                return;
+            }
+            self.confirming = false;
+            self.set_pending_update(None);
+            let version = self.slots.running_version();
+            log::info!("Firmware {} ({}) passed its health check", sha256, version);
+            self.report_tx
+                .send(MANAGEReport::FirmwareUpdated { sha256, version })
+                .unwrap();
+        } else if now - self.booted_at >= FIRMWARE_CONFIRM_TIMEOUT {
+            // Reported by the previous image once it is running again
+            log::error!("Firmware {} failed its health check, rolling back", sha256);
+            self.confirming = false;
+            self.slots.rollback();
+        }
+    }
+
+    fn pending_update(&self) -> Option<String> {
+        self.storage
+            .load::<Option<String>>(PENDING_UPDATE_KEY)
+            .flatten()
+    }
+
+    fn set_pending_update(&mut self, sha256: Option<String>) {
+        if !self.storage.store(PENDING_UPDATE_KEY, &sha256) {
+            log::error!("Failed to store the pending firmware update");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+    use crate::guardian_device_identity::firmware_signature;
+    use crate::guardian_hal::fake::FakeFirmwareSlots;
+    use crate::guardian_storage::MemoryStore;
+
+    const SECRET: [u8; 32] = [7; 32];
+    const IMAGE: &[u8] = b"guardian firmware image";
+
+    fn load_updater(
+        slots: FakeFirmwareSlots,
+        store: &MemoryStore,
+    ) -> (FirmwareUpdater<FakeFirmwareSlots>, Receiver<MANAGEReport>) {
+        let (report_tx, report_rx) = channel();
+        let identity = DeviceIdentity::new("02005e000001".to_string(), SECRET.to_vec());
+        let storage = GuardianStorage::new(store.clone());
+        let updater = FirmwareUpdater::new(slots, identity, storage, report_tx, Instant::now());
+        (updater, report_rx)
+    }
+
+    fn install(updater: &mut FirmwareUpdater<FakeFirmwareSlots>, image: &[u8]) -> CommandOutcome {
+        let sha256 = hex::encode(Sha256::digest(image));
+        let signature = firmware_signature(&SECRET, "02005e000001", &sha256);
+        let last_tick = AtomicInstant::now();
+        updater.install(
+            "https://manage/firmware.bin",
+            &sha256,
+            &signature,
+            &last_tick,
+        )
+    }
+
+    #[test]
+    fn verified_image_is_activated() {
+        let store = MemoryStore::default();
+        let slots = FakeFirmwareSlots::new(IMAGE);
+        let (mut updater, _report_rx) = load_updater(slots.clone(), &store);
+
+        assert_eq!(install(&mut updater, IMAGE), CommandOutcome::Success);
+        assert!(slots.activated());
+
+        // A bad signature is turned down before anything is downloaded
+        let sha256 = hex::encode(Sha256::digest(IMAGE));
+        let last_tick = AtomicInstant::now();
+        assert!(matches!(
+            updater.install("https://manage/firmware.bin", &sha256, "00", &last_tick),
+            CommandOutcome::Rejected { .. }
+        ));
+    }
+
+    #[test]
+    fn image_not_matching_its_sha256_is_not_booted() {
+        let store = MemoryStore::default();
+        let slots = FakeFirmwareSlots::new(IMAGE);
+        let (mut updater, _report_rx) = load_updater(slots.clone(), &store);
+
+        assert!(matches!(
+            install(&mut updater, b"another image"),
+            CommandOutcome::Rejected { .. }
+        ));
+        assert!(!slots.activated());
+    }
+
+    #[test]
+    fn new_image_is_kept_once_healthy() {
+        let store = MemoryStore::default();
+        let (mut updater, _report_rx) = load_updater(FakeFirmwareSlots::new(IMAGE), &store);
+        install(&mut updater, IMAGE);
+
+        // After the restart into the new image
+        let slots = FakeFirmwareSlots::new(IMAGE);
+        slots.set_pending_verify(true);
+        let (mut updater, report_rx) = load_updater(slots.clone(), &store);
+        updater.check_health(false, Instant::now());
+        updater.check_health(true, Instant::now());
+        assert!(slots.marked_valid());
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::FirmwareUpdated { .. })
+        ));
+
+        // Later checks leave the image alone
+        updater.check_health(false, Instant::now() + FIRMWARE_CONFIRM_TIMEOUT);
+        assert!(!slots.rolled_back());
+    }
+
+    #[test]
+    fn unhealthy_image_is_rolled_back_and_reported() {
+        let store = MemoryStore::default();
+        let (mut updater, _report_rx) = load_updater(FakeFirmwareSlots::new(IMAGE), &store);
+        install(&mut updater, IMAGE);
+
+        let slots = FakeFirmwareSlots::new(IMAGE);
+        slots.set_pending_verify(true);
+        let (mut updater, _report_rx) = load_updater(slots.clone(), &store);
+        updater.check_health(false, Instant::now() + FIRMWARE_CONFIRM_TIMEOUT);
+        assert!(slots.rolled_back());
+
+        // The previous image reports the rollback once
+        let (_updater, report_rx) = load_updater(FakeFirmwareSlots::new(IMAGE), &store);
+        let sha256 = hex::encode(Sha256::digest(IMAGE));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::FirmwareRolledBack {
+                sha256: rolled_back,
+                ..
+            }) if rolled_back == sha256
+        ));
+        let (_updater, report_rx) = load_updater(FakeFirmwareSlots::new(IMAGE), &store);
+        assert!(report_rx.try_recv().is_err());
+    }
+}
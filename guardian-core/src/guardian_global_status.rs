@@ -0,0 +1,20 @@
+use std::sync::atomic::{AtomicBool, AtomicU32};
+
+// Most readers a controller serves on its RS-485 bus
+pub const MAX_READERS: usize = 8;
+
+// Global status flags for Guardian System
+// Peripheral Device (PD) status, indexed by reader id
+pub static PD_ONLINE: [AtomicBool; MAX_READERS] = [const { AtomicBool::new(false) }; MAX_READERS];
+
+// MANAGE websocket is up and the session is authenticated
+pub static MANAGE_CONNECTED: AtomicBool = AtomicBool::new(false);
+
+// Bytes dropped because the OSDP serial RX queue was full
+pub static OSDP_RX_QUEUE_FULL: AtomicU32 = AtomicU32::new(0);
+
+// Times the MANAGE websocket was reconnected since boot
+pub static MANAGE_RECONNECTS: AtomicU32 = AtomicU32::new(0);
+
+// Every door loop ticked recently at the last health check
+pub static DOORS_TICKING: AtomicBool = AtomicBool::new(false);
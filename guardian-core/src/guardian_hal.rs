@@ -0,0 +1,343 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::guardian_firmware_update::ImageCheck;
+use super::guardian_telemetry::NetworkInfo;
+
+// Digital output driving a relay or a motor controller input
+pub trait OutputPin {
+    type Error: Debug;
+
+    fn set_high(&mut self) -> Result<(), Self::Error>;
+    fn set_low(&mut self) -> Result<(), Self::Error>;
+}
+
+// Digital input reading a switch or a sensor
+pub trait InputPin {
+    fn is_high(&self) -> bool;
+}
+
+// Monotonic time source, replaced by a manual clock in tests
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub type SharedClock = Arc<dyn Clock + Send + Sync>;
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// Link to MANAGE that reports are sent over
+pub trait Transport {
+    type Error: Debug;
+
+    // The socket is up, reports may only be sent once the session is connected
+    fn is_open(&self) -> bool;
+    // MANAGE is reachable and the session has been authenticated
+    fn is_connected(&self) -> bool;
+    fn send_text(&mut self, text: &str) -> Result<(), Self::Error>;
+}
+
+// The OTA slots the firmware boots from
+pub trait FirmwareSlots {
+    type Error: Debug;
+
+    // Download the image at url over HTTPS into the inactive slot, passing every chunk to
+    // image_check, and boot it from the next restart on if image_check.finish() agrees
+    fn install(&mut self, url: &str, image_check: &mut ImageCheck) -> Result<(), Self::Error>;
+    // The running image was just installed and has not been marked valid yet
+    fn is_pending_verify(&self) -> bool;
+    // Keep booting the running image
+    fn mark_valid(&mut self) -> Result<(), Self::Error>;
+    // Go back to the previous image, restarting into it
+    fn rollback(&mut self);
+    fn restart(&mut self);
+    fn running_version(&self) -> String;
+}
+
+// Platform figures for the telemetry report
+pub trait SystemMetrics {
+    // Bytes of heap free now and at the lowest point since boot
+    fn free_heap(&self) -> u32;
+    fn min_free_heap(&self) -> u32;
+    fn network(&self) -> NetworkInfo;
+}
+
+// The chip's task watchdog, resetting it when the subscribed thread stops feeding it
+pub trait SystemWatchdog {
+    // Subscribe the calling thread to the task watchdog
+    fn subscribe(&mut self);
+    fn feed(&mut self);
+    // Why the chip last reset, e.g. "power_on", "panic" or "task_watchdog"
+    fn reset_reason(&self) -> String;
+    fn reboot(&mut self);
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::{
+        Clock, FirmwareSlots, InputPin, NetworkInfo, OutputPin, SystemMetrics, SystemWatchdog,
+        Transport,
+    };
+    use crate::guardian_firmware_update::ImageCheck;
+
+    #[derive(Clone)]
+    pub struct ManualClock {
+        now: Arc<Mutex<Instant>>,
+    }
+
+    impl ManualClock {
+        pub fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(Instant::now())),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Default for ManualClock {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct FakeOutputPin {
+        high: Rc<Cell<bool>>,
+        broken: Rc<Cell<bool>>,
+        stuck_low: Rc<Cell<bool>>,
+    }
+
+    impl FakeOutputPin {
+        pub fn is_high(&self) -> bool {
+            self.high.get()
+        }
+
+        // Fail every write from now on, like a GPIO driver error
+        pub fn break_output(&self) {
+            self.broken.set(true);
+        }
+
+        // Fail only the writes that drive the output high, the output can still be released
+        pub fn stick_low(&self) {
+            self.stuck_low.set(true);
+        }
+
+        fn set(&self, high: bool) -> Result<(), &'static str> {
+            if self.broken.get() || (high && self.stuck_low.get()) {
+                return Err("broken output");
+            }
+            self.high.set(high);
+            Ok(())
+        }
+    }
+
+    impl OutputPin for FakeOutputPin {
+        type Error = &'static str;
+
+        fn set_high(&mut self) -> Result<(), &'static str> {
+            self.set(true)
+        }
+
+        fn set_low(&mut self) -> Result<(), &'static str> {
+            self.set(false)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct FakeInputPin {
+        high: Rc<Cell<bool>>,
+    }
+
+    impl FakeInputPin {
+        pub fn set(&self, high: bool) {
+            self.high.set(high);
+        }
+    }
+
+    impl InputPin for FakeInputPin {
+        fn is_high(&self) -> bool {
+            self.high.get()
+        }
+    }
+
+    #[derive(Default)]
+    pub struct FakeTransport {
+        pub open: bool,
+        pub connected: bool,
+        pub fail_sends: bool,
+        pub sent: Vec<String>,
+    }
+
+    impl Transport for FakeTransport {
+        type Error = ();
+
+        fn is_open(&self) -> bool {
+            self.open || self.connected
+        }
+
+        fn is_connected(&self) -> bool {
+            self.connected
+        }
+
+        fn send_text(&mut self, text: &str) -> Result<(), ()> {
+            if self.fail_sends {
+                return Err(());
+            }
+            self.sent.push(text.to_string());
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct FakeFirmwareSlots {
+        image: Rc<Vec<u8>>,
+        pending_verify: Rc<Cell<bool>>,
+        activated: Rc<Cell<bool>>,
+        marked_valid: Rc<Cell<bool>>,
+        rolled_back: Rc<Cell<bool>>,
+    }
+
+    impl FakeFirmwareSlots {
+        // Slots downloading the given image from any url
+        pub fn new(image: &[u8]) -> Self {
+            Self {
+                image: Rc::new(image.to_vec()),
+                ..Default::default()
+            }
+        }
+
+        pub fn set_pending_verify(&self, pending_verify: bool) {
+            self.pending_verify.set(pending_verify);
+        }
+
+        pub fn activated(&self) -> bool {
+            self.activated.get()
+        }
+
+        pub fn marked_valid(&self) -> bool {
+            self.marked_valid.get()
+        }
+
+        pub fn rolled_back(&self) -> bool {
+            self.rolled_back.get()
+        }
+    }
+
+    impl FirmwareSlots for FakeFirmwareSlots {
+        type Error = &'static str;
+
+        fn install(
+            &mut self,
+            _url: &str,
+            image_check: &mut ImageCheck,
+        ) -> Result<(), &'static str> {
+            for chunk in self.image.chunks(4) {
+                image_check.update(chunk);
+            }
+            if !image_check.finish() {
+                return Err("image mismatch");
+            }
+            self.activated.set(true);
+            Ok(())
+        }
+
+        fn is_pending_verify(&self) -> bool {
+            self.pending_verify.get()
+        }
+
+        fn mark_valid(&mut self) -> Result<(), &'static str> {
+            self.marked_valid.set(true);
+            Ok(())
+        }
+
+        fn rollback(&mut self) {
+            self.rolled_back.set(true);
+        }
+
+        fn restart(&mut self) {}
+
+        fn running_version(&self) -> String {
+            "0.1.0".to_string()
+        }
+    }
+
+    pub struct FakeSystemMetrics;
+
+    impl SystemMetrics for FakeSystemMetrics {
+        fn free_heap(&self) -> u32 {
+            120_000
+        }
+
+        fn min_free_heap(&self) -> u32 {
+            80_000
+        }
+
+        fn network(&self) -> NetworkInfo {
+            NetworkInfo {
+                link_up: true,
+                ip: Some("192.0.2.10".to_string()),
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct FakeSystemWatchdog {
+        reset_reason: String,
+        feeds: Rc<Cell<u32>>,
+        rebooted: Rc<Cell<bool>>,
+    }
+
+    impl FakeSystemWatchdog {
+        pub fn new(reset_reason: &str) -> Self {
+            Self {
+                reset_reason: reset_reason.to_string(),
+                ..Default::default()
+            }
+        }
+
+        pub fn feeds(&self) -> u32 {
+            self.feeds.get()
+        }
+
+        pub fn rebooted(&self) -> bool {
+            self.rebooted.get()
+        }
+    }
+
+    impl SystemWatchdog for FakeSystemWatchdog {
+        fn subscribe(&mut self) {}
+
+        fn feed(&mut self) {
+            self.feeds.set(self.feeds.get() + 1);
+        }
+
+        fn reset_reason(&self) -> String {
+            self.reset_reason.clone()
+        }
+
+        fn reboot(&mut self) {
+            self.rebooted.set(true);
+        }
+    }
+}
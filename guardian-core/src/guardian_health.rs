@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+// The door loop ticks every 100ms, anything older means it is stuck
+pub const DOOR_TICK_MAX_AGE: Duration = Duration::from_secs(2);
+
+pub fn doors_ticking(last_door_tick_age: Duration) -> bool {
+    last_door_tick_age < DOOR_TICK_MAX_AGE
+}
+
+// Guardian is healthy while the door loop is ticking and every reader is online
+pub fn is_healthy(last_door_tick_age: Duration, readers_online: bool) -> bool {
+    doors_ticking(last_door_tick_age) && readers_online
+}
+
+// A new firmware image is kept once it drives the doors and MANAGE accepted its session.
+// Readers are left out, an unplugged one would otherwise roll back every update
+pub fn firmware_healthy(doors_ticking: bool, manage_connected: bool) -> bool {
+    doors_ticking && manage_connected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn healthy_while_ticking_and_online() {
+        assert!(is_healthy(Duration::from_millis(1999), true));
+        assert!(!is_healthy(Duration::from_secs(2), true));
+        assert!(!is_healthy(Duration::from_millis(100), false));
+    }
+
+    #[test]
+    fn firmware_is_kept_without_readers() {
+        assert!(firmware_healthy(
+            doors_ticking(Duration::from_millis(100)),
+            true
+        ));
+        assert!(!firmware_healthy(
+            doors_ticking(Duration::from_secs(2)),
+            true
+        ));
+        assert!(!firmware_healthy(
+            doors_ticking(Duration::from_millis(100)),
+            false
+        ));
+    }
+}
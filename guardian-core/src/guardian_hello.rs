@@ -0,0 +1,56 @@
+use std::time::Instant;
+
+use super::guardian_config::{DoorKind, GuardianConfig, ReaderConfig};
+use super::manage_command::{MANAGEReport, SUPPORTED_COMMANDS};
+
+// Commit Guardian was built from
+pub const GIT_HASH: &str = env!("GUARDIAN_GIT_HASH");
+
+// What the controller runs and drives, announced to MANAGE on every connect
+pub struct ControllerInfo {
+    firmware_version: String,
+    idf_version: Option<String>,
+    reset_reason: String,
+    mac_address: String,
+    doors: Vec<DoorKind>,
+    readers: Vec<ReaderConfig>,
+    booted_at: Instant,
+}
+
+impl ControllerInfo {
+    pub fn new(
+        firmware_version: &str,
+        idf_version: Option<String>,
+        reset_reason: String,
+        mac_address: String,
+        config: &GuardianConfig,
+        booted_at: Instant,
+    ) -> Self {
+        Self {
+            firmware_version: firmware_version.to_string(),
+            idf_version,
+            reset_reason,
+            mac_address,
+            doors: config.doors.iter().map(|door| door.door_type).collect(),
+            readers: config.readers.clone(),
+            booted_at,
+        }
+    }
+
+    pub fn hello(&self, now: Instant) -> MANAGEReport {
+        MANAGEReport::Hello {
+            firmware_version: self.firmware_version.clone(),
+            git_hash: GIT_HASH.to_string(),
+            idf_version: self.idf_version.clone(),
+            reset_reason: self.reset_reason.clone(),
+            uptime_secs: now.saturating_duration_since(self.booted_at).as_secs(),
+            mac_address: self.mac_address.clone(),
+            doors: self.doors.clone(),
+            readers: self.readers.clone(),
+            commands: SUPPORTED_COMMANDS
+                .iter()
+                .map(|command| command.to_string())
+                .collect(),
+        }
+    }
+}
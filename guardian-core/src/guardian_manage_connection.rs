@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+// Backoff before the first reconnect, doubled on every failed attempt up to the maximum
+pub const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+pub const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(120);
+// A client that hasn't opened its socket by then is given up on
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    // A client is trying to reach MANAGE
+    Connecting { since: Instant },
+    // The socket is up, the session may still be authenticating
+    Connected,
+    // No client, the next one is created once the delay is over
+    Backoff { until: Instant },
+}
+
+// What the owner of the client has to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionAction {
+    Wait,
+    // Create a new client
+    Connect,
+    // Drop the current client
+    Teardown,
+}
+
+// Decides when the MANAGE websocket client is created and torn down, backing off
+// exponentially with jitter until a session is authenticated again
+pub struct ManageConnection {
+    state: ConnectionState,
+    failures: u32,
+}
+
+impl ManageConnection {
+    // The first client is created right away
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: ConnectionState::Backoff { until: now },
+            failures: 0,
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    // Called on every loop iteration with the state of the current client
+    pub fn update(&mut self, open: bool, authenticated: bool, now: Instant) -> ConnectionAction {
+        match self.state {
+            ConnectionState::Backoff { until } if now >= until => {
+                self.state = ConnectionState::Connecting { since: now };
+                ConnectionAction::Connect
+            }
+            ConnectionState::Backoff { .. } => ConnectionAction::Wait,
+            ConnectionState::Connecting { since } => {
+                if open {
+                    log::info!("MANAGE socket open");
+                    self.state = ConnectionState::Connected;
+                    ConnectionAction::Wait
+                } else if now - since >= CONNECT_TIMEOUT {
+                    log::warn!("Gave up connecting to MANAGE");
+                    self.back_off(now)
+                } else {
+                    ConnectionAction::Wait
+                }
+            }
+            ConnectionState::Connected => {
+                // Only a session MANAGE accepted counts as a successful attempt
+                if authenticated {
+                    self.failures = 0;
+                }
+                if open {
+                    ConnectionAction::Wait
+                } else {
+                    log::warn!("MANAGE socket closed");
+                    self.back_off(now)
+                }
+            }
+        }
+    }
+
+    fn back_off(&mut self, now: Instant) -> ConnectionAction {
+        let mut random = [0u8; 4];
+        getrandom::getrandom(&mut random).unwrap();
+        let delay = backoff_delay(self.failures, u32::from_le_bytes(random));
+        log::info!("Reconnecting to MANAGE in {:?}", delay);
+        self.failures = self.failures.saturating_add(1);
+        self.state = ConnectionState::Backoff { until: now + delay };
+        ConnectionAction::Teardown
+    }
+}
+
+// Half the doubled delay is fixed, the other half random, so a fleet that lost MANAGE at
+// once doesn't reconnect in lockstep
+fn backoff_delay(failures: u32, random: u32) -> Duration {
+    let delay = RECONNECT_MIN_DELAY
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(RECONNECT_MAX_DELAY);
+    delay / 2 + (delay / 2).mul_f64(random as f64 / u32::MAX as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff_delay(0, 0), RECONNECT_MIN_DELAY / 2);
+        assert_eq!(backoff_delay(0, u32::MAX), RECONNECT_MIN_DELAY);
+        assert_eq!(backoff_delay(3, u32::MAX), RECONNECT_MIN_DELAY * 8);
+        assert_eq!(backoff_delay(40, 0), RECONNECT_MAX_DELAY / 2);
+        assert_eq!(backoff_delay(40, u32::MAX), RECONNECT_MAX_DELAY);
+    }
+
+    #[test]
+    fn client_is_torn_down_and_recreated_after_backoff() {
+        let now = Instant::now();
+        let mut connection = ManageConnection::new(now);
+        assert_eq!(
+            connection.update(false, false, now),
+            ConnectionAction::Connect
+        );
+        assert_eq!(connection.update(true, false, now), ConnectionAction::Wait);
+        assert_eq!(connection.state(), ConnectionState::Connected);
+
+        // Closed before MANAGE accepted the session
+        assert_eq!(
+            connection.update(false, false, now),
+            ConnectionAction::Teardown
+        );
+        assert_eq!(connection.update(false, false, now), ConnectionAction::Wait);
+        let later = now + RECONNECT_MIN_DELAY * 2;
+        assert_eq!(
+            connection.update(false, false, later),
+            ConnectionAction::Connect
+        );
+
+        // A client that never opens is given up on
+        assert_eq!(
+            connection.update(false, false, later + CONNECT_TIMEOUT),
+            ConnectionAction::Teardown
+        );
+        let ConnectionState::Backoff { until } = connection.state() else {
+            panic!("not backing off");
+        };
+        assert!(until >= later + CONNECT_TIMEOUT + RECONNECT_MIN_DELAY);
+
+        // An authenticated session starts the backoff over
+        assert_eq!(
+            connection.update(false, false, until),
+            ConnectionAction::Connect
+        );
+        connection.update(true, false, until);
+        connection.update(true, true, until);
+        connection.update(false, false, until);
+        let ConnectionState::Backoff { until: next } = connection.state() else {
+            panic!("not backing off");
+        };
+        assert!(next - until <= RECONNECT_MIN_DELAY);
+    }
+}
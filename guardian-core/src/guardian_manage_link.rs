@@ -0,0 +1,252 @@
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use super::guardian_hal::Transport;
+use super::guardian_hello::ControllerInfo;
+use super::guardian_report_journal::ReportJournal;
+use super::manage_command::MANAGEReport;
+
+// Delivers reports to MANAGE, journaling whatever can't be sent
+pub struct ManageLink {
+    report_journal: Arc<Mutex<ReportJournal>>,
+    controller_info: ControllerInfo,
+    was_connected: bool,
+}
+
+impl ManageLink {
+    pub fn new(report_journal: Arc<Mutex<ReportJournal>>, controller_info: ControllerInfo) -> Self {
+        Self {
+            report_journal,
+            controller_info,
+            was_connected: false,
+        }
+    }
+
+    // Replay the journal, then send reports as they come in until the deadline
+    pub fn pump<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        report_rx: &Receiver<MANAGEReport>,
+        deadline: Instant,
+    ) {
+        self.replay_journal(transport);
+        while let Ok(report) = report_rx.recv_deadline(deadline) {
+            self.deliver(transport, report);
+        }
+    }
+
+    // Greet MANAGE, then replay journaled reports in order once it is reachable
+    pub fn replay_journal<T: Transport>(&mut self, transport: &mut T) {
+        let is_connected = transport.is_connected();
+        if is_connected && !self.was_connected {
+            let hello = self.controller_info.hello(Instant::now());
+            if transport
+                .send_text(&serde_json::to_string(&hello).unwrap())
+                .is_err()
+            {
+                log::error!("Failed to send hello to MANAGE!");
+            }
+        }
+        let mut journal = self.report_journal.lock().unwrap();
+        if is_connected && !self.was_connected {
+            journal.restart_replay();
+        }
+        self.was_connected = is_connected;
+
+        if is_connected {
+            while let Some((sequence, replay)) = journal.next_replay() {
+                match transport.send_text(&serde_json::to_string(&replay).unwrap()) {
+                    Ok(_) => {
+                        log::info!("Replayed report #{} to MANAGE!", sequence);
+                        journal.mark_replayed(sequence);
+                    }
+                    Err(_) => {
+                        log::error!("Failed to replay report #{} to MANAGE!", sequence);
+                        break;
+                    }
+                }
+            }
+        }
+        journal.tick();
+    }
+
+    pub fn deliver<T: Transport>(&mut self, transport: &mut T, report: MANAGEReport) {
+        // Handshake reports belong to the current socket only
+        if report.is_handshake() {
+            if !transport.is_open() {
+                return;
+            }
+            if transport
+                .send_text(&serde_json::to_string(&report).unwrap())
+                .is_err()
+            {
+                log::error!("Failed to send handshake to MANAGE!");
+            }
+            return;
+        }
+
+        // Don't bother sending while MANAGE is unreachable, and queue up behind a replay
+        // that stalled so reports reach MANAGE in order
+        if !transport.is_connected()
+            || (report.is_journaled() && self.report_journal.lock().unwrap().replay_pending())
+        {
+            self.journal(report);
+            return;
+        }
+
+        match transport.send_text(&serde_json::to_string(&report).unwrap()) {
+            Ok(_) => {
+                log::info!("Sent report to MANAGE!: {:?}", report);
+            }
+            Err(_) => {
+                log::error!("Failed to send report to MANAGE!: {:?}", report);
+                self.journal(report);
+            }
+        }
+    }
+
+    fn journal(&self, report: MANAGEReport) {
+        if report.is_journaled() {
+            self.report_journal.lock().unwrap().push(report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::guardian_config::GuardianConfig;
+    use crate::guardian_hal::fake::{FakeTransport, ManualClock};
+    use crate::guardian_storage::{GuardianStorage, MemoryStore};
+
+    fn manage_link() -> ManageLink {
+        let journal = ReportJournal::load(
+            GuardianStorage::new(MemoryStore::default()),
+            Arc::new(ManualClock::new()),
+        );
+        let controller_info = ControllerInfo::new(
+            "0.1.0",
+            None,
+            "power_on".to_string(),
+            "02005e000001".to_string(),
+            &GuardianConfig::default(),
+            Instant::now(),
+        );
+        ManageLink::new(Arc::new(Mutex::new(journal)), controller_info)
+    }
+
+    #[test]
+    fn undelivered_reports_are_replayed_after_reconnect() {
+        let mut link = manage_link();
+        let mut transport = FakeTransport::default();
+        link.replay_journal(&mut transport);
+
+        // Heartbeats are dropped, everything else is journaled
+        link.deliver(&mut transport, MANAGEReport::Heartbeat { is_healthy: true });
+        link.deliver(&mut transport, MANAGEReport::DoorForcedOpen { door_id: 0 });
+        transport.connected = true;
+        transport.fail_sends = true;
+        link.deliver(&mut transport, MANAGEReport::DoorClosed { door_id: 0 });
+        assert!(transport.sent.is_empty());
+
+        transport.fail_sends = false;
+        link.replay_journal(&mut transport);
+        assert_eq!(transport.sent.len(), 3);
+        assert!(transport.sent[0].contains("\"command\":\"hello\""));
+        assert!(transport.sent[1].contains("\"command\":\"journal.replay\""));
+        assert!(transport.sent[1].contains("\"command\":\"door.forced_open\""));
+        assert!(transport.sent[2].contains("\"command\":\"door.closed\""));
+
+        // Nothing is replayed twice on the same connection
+        link.replay_journal(&mut transport);
+        assert_eq!(transport.sent.len(), 3);
+
+        link.deliver(&mut transport, MANAGEReport::DoorOpened { door_id: 0 });
+        assert_eq!(
+            transport.sent[3],
+            "{\"command\":\"door.opened\",\"door_id\":0}"
+        );
+    }
+
+    #[test]
+    fn live_reports_do_not_overtake_a_stalled_replay() {
+        let mut link = manage_link();
+        let mut transport = FakeTransport::default();
+        link.deliver(&mut transport, MANAGEReport::DoorForcedOpen { door_id: 0 });
+
+        // The replay fails, the next report waits behind it
+        transport.connected = true;
+        transport.fail_sends = true;
+        link.replay_journal(&mut transport);
+        transport.fail_sends = false;
+        link.deliver(&mut transport, MANAGEReport::DoorClosed { door_id: 0 });
+        link.deliver(&mut transport, MANAGEReport::Heartbeat { is_healthy: true });
+        assert_eq!(transport.sent.len(), 1);
+        assert!(transport.sent[0].contains("\"command\":\"heartbeat\""));
+
+        link.replay_journal(&mut transport);
+        assert_eq!(transport.sent.len(), 3);
+        assert!(transport.sent[1].contains("\"command\":\"door.forced_open\""));
+        assert!(transport.sent[2].contains("\"command\":\"journal.replay\""));
+        assert!(transport.sent[2].contains("\"command\":\"door.closed\""));
+
+        // Once caught up reports go out directly again
+        link.deliver(&mut transport, MANAGEReport::DoorOpened { door_id: 0 });
+        assert_eq!(
+            transport.sent[3],
+            "{\"command\":\"door.opened\",\"door_id\":0}"
+        );
+    }
+
+    #[test]
+    fn hello_is_sent_on_every_connect() {
+        let mut link = manage_link();
+        let mut transport = FakeTransport {
+            connected: true,
+            ..Default::default()
+        };
+        link.replay_journal(&mut transport);
+        link.replay_journal(&mut transport);
+        assert_eq!(transport.sent.len(), 1);
+        let hello: serde_json::Value = serde_json::from_str(&transport.sent[0]).unwrap();
+        assert_eq!(hello["mac_address"], "02005e000001");
+        assert_eq!(hello["doors"], serde_json::json!(["lock_fail_secure"]));
+        assert!(hello["commands"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("door.unlock")));
+
+        transport.connected = false;
+        link.replay_journal(&mut transport);
+        transport.connected = true;
+        link.replay_journal(&mut transport);
+        assert_eq!(transport.sent.len(), 2);
+        assert!(transport.sent[1].contains("\"command\":\"hello\""));
+    }
+
+    #[test]
+    fn handshake_is_sent_before_the_session_is_connected() {
+        let mut link = manage_link();
+        let mut transport = FakeTransport::default();
+        let response = MANAGEReport::AuthResponse {
+            mac_address: "02005e000001".to_string(),
+            nonce: "00".to_string(),
+            signature: "00".to_string(),
+        };
+
+        // Dropped while the socket is down rather than journaled
+        link.deliver(&mut transport, response.clone());
+        assert!(transport.sent.is_empty());
+        assert!(link.report_journal.lock().unwrap().is_empty());
+
+        transport.open = true;
+        link.deliver(&mut transport, response);
+        link.deliver(&mut transport, MANAGEReport::DoorClosed { door_id: 0 });
+        assert_eq!(transport.sent.len(), 1);
+        assert!(transport.sent[0].contains("\"command\":\"auth.response\""));
+        assert_eq!(link.report_journal.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,404 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use libosdp::{OsdpEvent, OsdpStatusReport, OsdpStatusReportType};
+use serde::{Deserialize, Serialize};
+
+use super::guardian_access_cache::AccessCache;
+use super::guardian_card_format::decode_card;
+use super::guardian_config::ReaderConfig;
+use super::guardian_pin_entry::PinEntry;
+use super::manage_command::{CommandRequest, MANAGECommand, MANAGEReport};
+
+// osdp_LSTATR entries, see the osdp_LSTAT reply description
+const LOCAL_STATUS_TAMPER: u32 = 0;
+const LOCAL_STATUS_POWER: u32 = 1;
+
+// A status report carries up to 32 entries as a bit mask
+const STATUS_REPORT_MAX_ENTRIES: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReaderStatusType {
+    Input,
+    Output,
+    // Tamper of readers wired to the PD
+    Remote,
+}
+
+// libosdp keeps the fields of a status report private and only builds input and output
+// reports, so the others are read and built through its serde impls
+#[derive(Serialize, Deserialize)]
+struct StatusReportFields {
+    type_: OsdpStatusReportType,
+    nr_entries: usize,
+    mask: u32,
+}
+
+impl StatusReportFields {
+    fn read(status_report: &OsdpStatusReport) -> Self {
+        serde_json::from_value(serde_json::to_value(status_report).unwrap()).unwrap()
+    }
+
+    fn entry(&self, index: u32) -> bool {
+        (index as usize) < self.nr_entries && self.mask & (1 << index) != 0
+    }
+}
+
+// Local status report with the reader's tamper switch and power state, as sent by the reader
+pub fn local_status_report(tamper: bool, power_failure: bool) -> OsdpStatusReport {
+    let fields = StatusReportFields {
+        type_: OsdpStatusReportType::Local,
+        nr_entries: 2,
+        mask: (tamper as u32) << LOCAL_STATUS_TAMPER | (power_failure as u32) << LOCAL_STATUS_POWER,
+    };
+    serde_json::from_value(serde_json::to_value(fields).unwrap()).unwrap()
+}
+
+// Turns OSDP reader events into MANAGE reports and offline door commands
+pub struct OsdpEventHandler {
+    access_cache: Arc<Mutex<AccessCache>>,
+    report_tx: Sender<MANAGEReport>,
+    // Door thread command channels, indexed by door id
+    door_command_txs: Vec<Sender<CommandRequest>>,
+    // The configured readers and their keypads, indexed by reader id
+    readers: Vec<ReaderConfig>,
+    pin_entries: Vec<PinEntry>,
+    manage_connected: &'static AtomicBool,
+}
+
+impl OsdpEventHandler {
+    pub fn new(
+        access_cache: Arc<Mutex<AccessCache>>,
+        report_tx: Sender<MANAGEReport>,
+        door_command_txs: Vec<Sender<CommandRequest>>,
+        readers: Vec<ReaderConfig>,
+        manage_connected: &'static AtomicBool,
+    ) -> Self {
+        let pin_entries = readers
+            .iter()
+            .enumerate()
+            .map(|(reader, config)| PinEntry::new(reader as i32, config.card_and_pin))
+            .collect();
+        Self {
+            access_cache,
+            report_tx,
+            door_command_txs,
+            readers,
+            pin_entries,
+            manage_connected,
+        }
+    }
+
+    // Handle an event from the reader with the given reader id
+    pub fn handle_event(&mut self, reader: i32, event: OsdpEvent) {
+        match event {
+            OsdpEvent::CardRead(card_read_event) => {
+                log::info!("Card Read (reader {}): {:?}", reader, card_read_event);
+
+                // A misread card never gets to MANAGE or the allowlist
+                let decoded = match decode_card(&card_read_event) {
+                    Ok(decoded) => decoded,
+                    Err(error) => {
+                        log::warn!("Rejected card read (reader {}): {}", reader, error);
+                        return;
+                    }
+                };
+
+                // Fall back to the local allowlist while MANAGE is unreachable
+                if !self.manage_connected.load(Ordering::SeqCst) {
+                    // PINs aren't cached, a card+PIN reader stays shut
+                    let unlock_duration = if self.readers[reader as usize].card_and_pin {
+                        None
+                    } else {
+                        self.access_cache.lock().unwrap().check(&card_read_event)
+                    };
+                    if let Some(duration) = unlock_duration {
+                        log::info!("Offline Access Granted: {:?}", card_read_event);
+                        // Unlock the door the reader is at
+                        let door_id = self.readers[reader as usize].door_id;
+                        self.door_command_txs[door_id as usize]
+                            .send(CommandRequest::local(MANAGECommand::DoorUnlock {
+                                door_id,
+                                duration,
+                            }))
+                            .unwrap();
+                    } else {
+                        log::info!("Offline Access Denied: {:?}", card_read_event);
+                    }
+
+                    // Reported through the journal once MANAGE is reachable again
+                    let report = MANAGEReport::OfflineAccessDecision {
+                        reader,
+                        event: card_read_event,
+                        decoded,
+                        granted: unlock_duration.is_some(),
+                    };
+                    self.report_tx.send(report).unwrap();
+                    return;
+                }
+
+                let pin_entry = &mut self.pin_entries[reader as usize];
+                if let Some(report) = pin_entry.card_read(card_read_event, decoded, Instant::now())
+                {
+                    self.report_tx.send(report).unwrap();
+                }
+            }
+            OsdpEvent::KeyPress(key_press_event) => {
+                log::info!("Key Press (reader {}): {:?}", reader, key_press_event);
+                let pin_entry = &mut self.pin_entries[reader as usize];
+                for report in pin_entry.key_press(&key_press_event.data, Instant::now()) {
+                    self.report_tx.send(report).unwrap();
+                }
+            }
+            OsdpEvent::MfgReply(mfg_reply_event) => {
+                log::info!("Mfg Reply (reader {}): {:?}", reader, mfg_reply_event);
+                let report = MANAGEReport::OsdpMfgReply {
+                    reader,
+                    event: mfg_reply_event,
+                };
+                self.report_tx.send(report).unwrap();
+            }
+            OsdpEvent::Status(status_report) => {
+                log::info!("Status (reader {}): {:?}", reader, status_report);
+                self.report_tx
+                    .send(status_report_to_manage(reader, &status_report))
+                    .unwrap();
+            }
+        }
+    }
+
+    // Called on every refresh to time out PIN entry
+    pub fn poll(&mut self, now: Instant) {
+        for pin_entry in &mut self.pin_entries {
+            if let Some(report) = pin_entry.poll(now) {
+                self.report_tx.send(report).unwrap();
+            }
+        }
+    }
+}
+
+fn status_report_to_manage(reader: i32, status_report: &OsdpStatusReport) -> MANAGEReport {
+    let fields = StatusReportFields::read(status_report);
+    let status_type = match fields.type_ {
+        OsdpStatusReportType::Input => ReaderStatusType::Input,
+        OsdpStatusReportType::Output => ReaderStatusType::Output,
+        OsdpStatusReportType::Remote => ReaderStatusType::Remote,
+        OsdpStatusReportType::Local => {
+            let tamper = fields.entry(LOCAL_STATUS_TAMPER);
+            let power_failure = fields.entry(LOCAL_STATUS_POWER);
+            if tamper || power_failure {
+                log::warn!(
+                    "Reader {} tamper: {}, power failure: {}",
+                    reader,
+                    tamper,
+                    power_failure
+                );
+            }
+            return MANAGEReport::OsdpLocalStatus {
+                reader,
+                tamper,
+                power_failure,
+            };
+        }
+    };
+    MANAGEReport::OsdpStatus {
+        reader,
+        status_type,
+        entries: (0..fields.nr_entries.min(STATUS_REPORT_MAX_ENTRIES) as u32)
+            .map(|index| fields.entry(index))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use libosdp::{OsdpCardFormats, OsdpEventCardRead, OsdpEventKeyPress};
+
+    use super::*;
+    use crate::guardian_config::ReaderRole;
+    use crate::guardian_storage::{GuardianStorage, MemoryStore};
+
+    fn reader(door_id: u32, card_and_pin: bool) -> ReaderConfig {
+        ReaderConfig {
+            address: 0,
+            name: "reader".to_string(),
+            role: ReaderRole::Entry,
+            door_id,
+            card_and_pin,
+        }
+    }
+
+    fn handler(
+        manage_connected: &'static AtomicBool,
+    ) -> (
+        OsdpEventHandler,
+        Receiver<MANAGEReport>,
+        Receiver<CommandRequest>,
+    ) {
+        let mut access_cache = AccessCache::load(GuardianStorage::new(MemoryStore::default()));
+        access_cache.handle_command(MANAGECommand::AccessCacheAdd {
+            credential: "c0ffee".to_string(),
+        });
+        let (report_tx, report_rx) = channel();
+        let (command_tx, command_rx) = channel();
+        let (other_door_tx, _other_door_rx) = channel();
+        // Reader 0 is at door 1, readers 1 and 2 at door 0 with reader 2 needing card+PIN
+        let handler = OsdpEventHandler::new(
+            Arc::new(Mutex::new(access_cache)),
+            report_tx,
+            vec![other_door_tx, command_tx],
+            vec![reader(1, false), reader(0, false), reader(0, true)],
+            manage_connected,
+        );
+        (handler, report_rx, command_rx)
+    }
+
+    fn card_read(data: Vec<u8>) -> OsdpEvent {
+        OsdpEvent::CardRead(OsdpEventCardRead {
+            data,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn card_reads_go_to_manage_while_connected() {
+        static CONNECTED: AtomicBool = AtomicBool::new(true);
+        let (mut handler, report_rx, command_rx) = handler(&CONNECTED);
+
+        handler.handle_event(1, card_read(vec![0xc0, 0xff, 0xee]));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OsdpCardRead { reader: 1, .. })
+        ));
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn card_reads_are_decided_locally_while_offline() {
+        static CONNECTED: AtomicBool = AtomicBool::new(false);
+        let (mut handler, report_rx, command_rx) = handler(&CONNECTED);
+
+        handler.handle_event(0, card_read(vec![0xc0, 0xff, 0xee]));
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(CommandRequest {
+                request_id: None,
+                command: MANAGECommand::DoorUnlock {
+                    door_id: 1,
+                    duration: 5,
+                },
+            })
+        ));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OfflineAccessDecision { granted: true, .. })
+        ));
+
+        handler.handle_event(0, card_read(vec![0xde, 0xad]));
+        assert!(command_rx.try_recv().is_err());
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OfflineAccessDecision { granted: false, .. })
+        ));
+    }
+
+    #[test]
+    fn reader_tamper_is_reported() {
+        static CONNECTED: AtomicBool = AtomicBool::new(true);
+        let (mut handler, report_rx, _command_rx) = handler(&CONNECTED);
+
+        handler.handle_event(1, OsdpEvent::Status(local_status_report(true, false)));
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OsdpLocalStatus {
+                reader: 1,
+                tamper: true,
+                power_failure: false,
+            })
+        ));
+
+        handler.handle_event(1, OsdpEvent::Status(OsdpStatusReport::new_input(3, 0b1001)));
+        let Ok(MANAGEReport::OsdpStatus {
+            status_type: ReaderStatusType::Input,
+            entries,
+            ..
+        }) = report_rx.try_recv()
+        else {
+            panic!("no input status reported");
+        };
+        assert_eq!(entries, [true, false, false]);
+
+        handler.handle_event(1, OsdpEvent::Status(OsdpStatusReport::new_output(2, 0b10)));
+        let Ok(MANAGEReport::OsdpStatus {
+            status_type: ReaderStatusType::Output,
+            entries,
+            ..
+        }) = report_rx.try_recv()
+        else {
+            panic!("no output status reported");
+        };
+        assert_eq!(entries, [false, true]);
+    }
+
+    #[test]
+    fn card_and_pin_reader_waits_for_the_pin() {
+        static CONNECTED: AtomicBool = AtomicBool::new(true);
+        let (mut handler, report_rx, _command_rx) = handler(&CONNECTED);
+
+        handler.handle_event(2, card_read(vec![0xc0, 0xff, 0xee]));
+        assert!(report_rx.try_recv().is_err());
+        handler.handle_event(
+            2,
+            OsdpEvent::KeyPress(OsdpEventKeyPress {
+                reader_no: 0,
+                data: b"1234\r".to_vec(),
+            }),
+        );
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OsdpPinEntered {
+                reader: 2,
+                pin,
+                card: Some(_),
+                ..
+            }) if pin == "1234"
+        ));
+    }
+
+    #[test]
+    fn card_and_pin_reader_denies_while_offline() {
+        static CONNECTED: AtomicBool = AtomicBool::new(false);
+        let (mut handler, report_rx, command_rx) = handler(&CONNECTED);
+
+        handler.handle_event(2, card_read(vec![0xc0, 0xff, 0xee]));
+        assert!(command_rx.try_recv().is_err());
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OfflineAccessDecision { granted: false, .. })
+        ));
+    }
+
+    #[test]
+    fn misread_cards_are_dropped() {
+        static CONNECTED: AtomicBool = AtomicBool::new(false);
+        let (mut handler, report_rx, command_rx) = handler(&CONNECTED);
+
+        // 26 bits with a broken leading parity bit
+        handler.handle_event(
+            1,
+            OsdpEvent::CardRead(OsdpEventCardRead {
+                format: OsdpCardFormats::Weigand,
+                nr_bits: 26,
+                data: vec![0x3d, 0x88, 0xeb, 0x80],
+                ..Default::default()
+            }),
+        );
+        assert!(command_rx.try_recv().is_err());
+        assert!(report_rx.try_recv().is_err());
+    }
+}
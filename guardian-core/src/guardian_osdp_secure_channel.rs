@@ -0,0 +1,311 @@
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use libosdp::{OsdpCommand, OsdpCommandKeyset, OsdpFlag, PdInfoBuilder};
+
+use super::guardian_storage::GuardianStorage;
+use super::manage_command::MANAGEReport;
+
+// Raw 16 byte SCBK per reader, kept in the "osdp_keys" NVS namespace
+const SCBK_LEN: usize = 16;
+
+// A reader that answers polls but has no secure channel after this failed the handshake
+pub const SC_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+// A reader that took the new key restarts the secure channel with it, one that keeps the
+// default key channel up for this long refused the KEYSET
+pub const KEYSET_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn scbk_key(reader: i32) -> String {
+    format!("scbk_{}", reader)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum KeyState {
+    // Secure channel turned off in the config
+    Disabled,
+    // No key stored yet, talk to the reader with the default key and send it this one
+    Install {
+        new_key: [u8; SCBK_LEN],
+    },
+    // KEYSET sent, waiting for the secure channel to drop and come back with the new key
+    Rotating {
+        new_key: [u8; SCBK_LEN],
+        sent_at: Instant,
+        dropped: bool,
+    },
+    // Using the stored key
+    Keyed {
+        key: [u8; SCBK_LEN],
+    },
+}
+
+// Secure channel setup and key rotation for one reader
+pub struct SecureChannel {
+    reader: i32,
+    state: KeyState,
+    storage: Arc<Mutex<GuardianStorage>>,
+    report_tx: Sender<MANAGEReport>,
+    insecure_since: Option<Instant>,
+    failure_reported: bool,
+}
+
+impl SecureChannel {
+    pub fn load(
+        reader: i32,
+        enabled: bool,
+        storage: Arc<Mutex<GuardianStorage>>,
+        report_tx: Sender<MANAGEReport>,
+    ) -> Self {
+        let stored_key = storage
+            .lock()
+            .unwrap()
+            .load_bytes(&scbk_key(reader))
+            .and_then(|key| <[u8; SCBK_LEN]>::try_from(key).ok());
+        let state = match (enabled, stored_key) {
+            (false, _) => KeyState::Disabled,
+            (true, Some(key)) => KeyState::Keyed { key },
+            (true, None) => {
+                log::warn!(
+                    "No SCBK stored for reader {}, installing a new one with the default key",
+                    reader
+                );
+                let mut new_key = [0u8; SCBK_LEN];
+                getrandom::getrandom(&mut new_key).unwrap();
+                KeyState::Install { new_key }
+            }
+        };
+        Self {
+            reader,
+            state,
+            storage,
+            report_tx,
+            insecure_since: None,
+            failure_reported: false,
+        }
+    }
+
+    // Events from the reader are only trusted over the secure channel
+    pub fn required(&self) -> bool {
+        self.state != KeyState::Disabled
+    }
+
+    // Set the key or install mode on the reader's PdInfo
+    pub fn pd_info(&self, builder: PdInfoBuilder) -> PdInfoBuilder {
+        match self.state {
+            KeyState::Disabled => builder,
+            KeyState::Install { .. } | KeyState::Rotating { .. } => {
+                builder.flag(OsdpFlag::InstallMode)
+            }
+            KeyState::Keyed { key } => builder.secure_channel_key(key),
+        }
+    }
+
+    // Called on every refresh, returns the KEYSET to send once the default key channel is up
+    pub fn poll(&mut self, online: bool, sc_active: bool, now: Instant) -> Option<OsdpCommand> {
+        if !self.required() || !online {
+            // An offline reader is not a handshake failure
+            self.insecure_since = None;
+            return None;
+        }
+
+        // Keep the new key once the reader has restarted the secure channel with it
+        if let KeyState::Rotating {
+            new_key,
+            sent_at,
+            dropped,
+        } = &mut self.state
+        {
+            let new_key = *new_key;
+            if !sc_active {
+                *dropped = true;
+            } else if *dropped {
+                self.keyed(new_key);
+            } else if now - *sent_at >= KEYSET_TIMEOUT {
+                log::warn!("Reader {} didn't take the new key, retrying", self.reader);
+                self.state = KeyState::Install { new_key };
+            }
+        }
+
+        if !sc_active {
+            // Report a reader stuck without secure channel once
+            let insecure_since = *self.insecure_since.get_or_insert(now);
+            if !self.failure_reported && now - insecure_since >= SC_HANDSHAKE_TIMEOUT {
+                self.failure_reported = true;
+                let install_mode = matches!(
+                    self.state,
+                    KeyState::Install { .. } | KeyState::Rotating { .. }
+                );
+                log::error!(
+                    "Reader {} failed the secure channel handshake (install mode: {})",
+                    self.reader,
+                    install_mode
+                );
+                self.report_tx
+                    .send(MANAGEReport::OsdpSecureChannelFailed {
+                        reader: self.reader,
+                        install_mode,
+                    })
+                    .unwrap();
+            }
+            return None;
+        }
+
+        if self.failure_reported {
+            log::info!("Secure channel with reader {} established", self.reader);
+        }
+        self.insecure_since = None;
+        self.failure_reported = false;
+
+        let KeyState::Install { new_key } = self.state else {
+            return None;
+        };
+        log::info!("Rotating reader {} off the default key", self.reader);
+        self.state = KeyState::Rotating {
+            new_key,
+            sent_at: now,
+            dropped: false,
+        };
+        Some(OsdpCommand::KeySet(OsdpCommandKeyset::new_scbk(new_key)))
+    }
+
+    // The KEYSET from poll couldn't be sent, it is sent again on the next poll
+    pub fn keyset_failed(&mut self) {
+        if let KeyState::Rotating { new_key, .. } = self.state {
+            self.state = KeyState::Install { new_key };
+        }
+    }
+
+    fn keyed(&mut self, key: [u8; SCBK_LEN]) {
+        // The reader only holds the key once the secure channel is back up with it
+        if !self
+            .storage
+            .lock()
+            .unwrap()
+            .store_bytes(&scbk_key(self.reader), &key)
+        {
+            log::error!(
+                "Failed to store SCBK, reader {} needs install mode again after a restart",
+                self.reader
+            );
+        }
+        log::info!("Reader {} is using the new key", self.reader);
+        self.state = KeyState::Keyed { key };
+        self.report_tx
+            .send(MANAGEReport::OsdpSecureChannelInstalled {
+                reader: self.reader,
+            })
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+    use crate::guardian_storage::{KeyValueStore, MemoryStore};
+
+    fn load_secure_channel(store: &MemoryStore) -> (SecureChannel, Receiver<MANAGEReport>) {
+        let (report_tx, report_rx) = channel();
+        let storage = Arc::new(Mutex::new(GuardianStorage::new(store.clone())));
+        (SecureChannel::load(0, true, storage, report_tx), report_rx)
+    }
+
+    #[test]
+    fn new_reader_is_rotated_off_the_default_key() {
+        let store = MemoryStore::default();
+        let (mut secure_channel, report_rx) = load_secure_channel(&store);
+        let now = Instant::now();
+
+        // Nothing is sent until the default key channel is up
+        assert!(secure_channel.poll(true, false, now).is_none());
+        let Some(OsdpCommand::KeySet(keyset)) = secure_channel.poll(true, true, now) else {
+            panic!("no KEYSET sent");
+        };
+        assert_eq!(keyset.data.len(), SCBK_LEN);
+        assert!(secure_channel.poll(true, true, now).is_none());
+
+        // The key is only kept once the channel restarted with it
+        assert!(store.get_blob("scbk_0").unwrap().is_none());
+        assert!(secure_channel.poll(true, false, now).is_none());
+        assert!(secure_channel.poll(true, true, now).is_none());
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OsdpSecureChannelInstalled { reader: 0 })
+        ));
+
+        // After a reboot the stored key is used
+        let (secure_channel, _report_rx) = load_secure_channel(&store);
+        assert_eq!(
+            secure_channel.state,
+            KeyState::Keyed {
+                key: keyset.data.try_into().unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn refused_keyset_is_sent_again() {
+        let store = MemoryStore::default();
+        let (mut secure_channel, report_rx) = load_secure_channel(&store);
+        let now = Instant::now();
+
+        // The default key channel stays up, so the reader didn't take the key
+        let Some(OsdpCommand::KeySet(keyset)) = secure_channel.poll(true, true, now) else {
+            panic!("no KEYSET sent");
+        };
+        assert!(secure_channel
+            .poll(true, true, now + KEYSET_TIMEOUT)
+            .is_some());
+
+        // Sending failed, try again on the next poll
+        secure_channel.keyset_failed();
+        let Some(OsdpCommand::KeySet(retry)) = secure_channel.poll(true, true, now) else {
+            panic!("no KEYSET sent");
+        };
+        assert_eq!(retry.data, keyset.data);
+        assert!(report_rx.try_recv().is_err());
+        assert!(store.get_blob("scbk_0").unwrap().is_none());
+    }
+
+    #[test]
+    fn handshake_failure_is_reported_once() {
+        let mut store = MemoryStore::default();
+        store.set_blob("scbk_0", &[7; SCBK_LEN]).unwrap();
+        let (mut secure_channel, report_rx) = load_secure_channel(&store);
+        let now = Instant::now();
+
+        // An offline reader is left to the online status
+        secure_channel.poll(false, false, now);
+        secure_channel.poll(false, false, now + SC_HANDSHAKE_TIMEOUT);
+        assert!(report_rx.try_recv().is_err());
+
+        secure_channel.poll(true, false, now);
+        secure_channel.poll(true, false, now + SC_HANDSHAKE_TIMEOUT);
+        secure_channel.poll(true, false, now + SC_HANDSHAKE_TIMEOUT * 2);
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::OsdpSecureChannelFailed {
+                reader: 0,
+                install_mode: false
+            })
+        ));
+        assert!(report_rx.try_recv().is_err());
+        assert!(secure_channel.poll(true, true, now).is_none());
+    }
+
+    #[test]
+    fn disabled_secure_channel_stores_nothing() {
+        let store = MemoryStore::default();
+        let (report_tx, report_rx) = channel();
+        let storage = Arc::new(Mutex::new(GuardianStorage::new(store.clone())));
+        let mut secure_channel = SecureChannel::load(0, false, storage.clone(), report_tx);
+
+        assert!(!secure_channel.required());
+        assert!(secure_channel.poll(true, false, Instant::now()).is_none());
+        assert!(secure_channel.poll(true, true, Instant::now()).is_none());
+        assert!(report_rx.try_recv().is_err());
+        assert!(storage.lock().unwrap().load_bytes("scbk_0").is_none());
+    }
+}
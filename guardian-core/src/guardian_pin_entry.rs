@@ -0,0 +1,174 @@
+use std::time::{Duration, Instant};
+
+use libosdp::OsdpEventCardRead;
+
+use super::guardian_card_format::DecodedCard;
+use super::manage_command::MANAGEReport;
+
+// OSDP keypad codes, digits come as ASCII
+const KEY_CLEAR: u8 = 0x7f;
+const KEY_ENTER: u8 = 0x0d;
+
+const PIN_MAX_LEN: usize = 12;
+
+// A PIN is entered once no key was pressed for this long, even without #
+pub const PIN_DIGIT_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a card waits for its PIN on a card+PIN reader
+pub const CARD_PIN_WINDOW: Duration = Duration::from_secs(15);
+
+// Assembles keypad presses of one reader into PINs, pairing them with a card for card+PIN
+pub struct PinEntry {
+    reader: i32,
+    card_and_pin: bool,
+    digits: String,
+    last_key_at: Option<Instant>,
+    card: Option<(OsdpEventCardRead, Option<DecodedCard>, Instant)>,
+}
+
+impl PinEntry {
+    pub fn new(reader: i32, card_and_pin: bool) -> Self {
+        Self {
+            reader,
+            card_and_pin,
+            digits: String::new(),
+            last_key_at: None,
+            card: None,
+        }
+    }
+
+    // A card+PIN reader holds the card back until its PIN is entered
+    pub fn card_read(
+        &mut self,
+        event: OsdpEventCardRead,
+        decoded: Option<DecodedCard>,
+        now: Instant,
+    ) -> Option<MANAGEReport> {
+        if !self.card_and_pin {
+            return Some(MANAGEReport::OsdpCardRead {
+                reader: self.reader,
+                event,
+                decoded,
+            });
+        }
+        log::info!("Reader {} waiting for the PIN of {:?}", self.reader, event);
+        self.digits.clear();
+        self.last_key_at = None;
+        self.card = Some((event, decoded, now));
+        None
+    }
+
+    pub fn key_press(&mut self, keys: &[u8], now: Instant) -> Vec<MANAGEReport> {
+        let mut reports = Vec::new();
+        for &key in keys {
+            match key {
+                b'0'..=b'9' if self.digits.len() < PIN_MAX_LEN => self.digits.push(key as char),
+                KEY_CLEAR => self.digits.clear(),
+                KEY_ENTER => reports.extend(self.submit(now)),
+                _ => {}
+            }
+        }
+        // The timeout runs from the last key of an unfinished PIN
+        self.last_key_at = (!self.digits.is_empty()).then_some(now);
+        reports
+    }
+
+    // Called on every refresh to finish a PIN left without # and drop a card left without PIN
+    pub fn poll(&mut self, now: Instant) -> Option<MANAGEReport> {
+        if self
+            .last_key_at
+            .is_some_and(|last_key_at| now - last_key_at >= PIN_DIGIT_TIMEOUT)
+        {
+            return self.submit(now);
+        }
+        if self
+            .card
+            .as_ref()
+            .is_some_and(|(_, _, read_at)| now - *read_at >= CARD_PIN_WINDOW)
+        {
+            log::info!("Reader {} got no PIN for the card in time", self.reader);
+            self.card = None;
+        }
+        None
+    }
+
+    fn submit(&mut self, now: Instant) -> Option<MANAGEReport> {
+        self.last_key_at = None;
+        if self.digits.is_empty() {
+            return None;
+        }
+        let (card, decoded) = match self.card.take() {
+            Some((card, decoded, read_at)) if now - read_at < CARD_PIN_WINDOW => {
+                (Some(card), decoded)
+            }
+            _ => (None, None),
+        };
+        Some(MANAGEReport::OsdpPinEntered {
+            reader: self.reader,
+            pin: std::mem::take(&mut self.digits),
+            card,
+            decoded,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card() -> OsdpEventCardRead {
+        OsdpEventCardRead {
+            data: vec![0xc0, 0xff, 0xee],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pin_is_sent_once_entered() {
+        let mut pin_entry = PinEntry::new(0, false);
+        let now = Instant::now();
+
+        // * starts over, # enters
+        assert!(pin_entry.key_press(b"12", now).is_empty());
+        assert!(pin_entry.key_press(&[KEY_CLEAR], now).is_empty());
+        let reports = pin_entry.key_press(&[b'4', b'2', KEY_ENTER], now);
+        assert!(matches!(
+            reports.as_slice(),
+            [MANAGEReport::OsdpPinEntered { reader: 0, pin, card: None, .. }] if pin == "42"
+        ));
+
+        // Without # the PIN is entered after the timeout
+        pin_entry.key_press(b"7", now);
+        assert!(pin_entry.poll(now + PIN_DIGIT_TIMEOUT / 2).is_none());
+        assert!(matches!(
+            pin_entry.poll(now + PIN_DIGIT_TIMEOUT),
+            Some(MANAGEReport::OsdpPinEntered { pin, .. }) if pin == "7"
+        ));
+        assert!(pin_entry.poll(now + PIN_DIGIT_TIMEOUT * 2).is_none());
+    }
+
+    #[test]
+    fn card_waits_for_its_pin() {
+        let mut pin_entry = PinEntry::new(1, true);
+        let now = Instant::now();
+
+        assert!(pin_entry.card_read(card(), None, now).is_none());
+        let reports = pin_entry.key_press(&[b'1', b'2', b'3', b'4', KEY_ENTER], now);
+        assert!(matches!(
+            reports.as_slice(),
+            [MANAGEReport::OsdpPinEntered {
+                reader: 1,
+                card: Some(card),
+                ..
+            }] if card.data == [0xc0, 0xff, 0xee]
+        ));
+
+        // A card whose PIN comes too late is dropped
+        pin_entry.card_read(card(), None, now);
+        assert!(pin_entry.poll(now + CARD_PIN_WINDOW).is_none());
+        let reports = pin_entry.key_press(&[b'1', KEY_ENTER], now + CARD_PIN_WINDOW);
+        assert!(matches!(
+            reports.as_slice(),
+            [MANAGEReport::OsdpPinEntered { card: None, .. }]
+        ));
+    }
+}
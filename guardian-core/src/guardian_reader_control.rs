@@ -0,0 +1,233 @@
+use libosdp::{
+    OsdpCommand, OsdpCommandBuzzer, OsdpCommandLed, OsdpCommandText, OsdpLedColor, OsdpLedParams,
+};
+use serde::{Deserialize, Serialize};
+
+use super::manage_command::{CommandOutcome, MANAGECommand};
+
+// OSDP control codes, see the osdp_LED, osdp_BUZ and osdp_TEXT command descriptions
+const LED_TEMPORARY_CANCEL: u8 = 1;
+const LED_TEMPORARY_SET: u8 = 2;
+const LED_PERMANENT_SET: u8 = 1;
+const BUZZER_DEFAULT_TONE: u8 = 2;
+const TEXT_PERMANENT: u8 = 1;
+const TEXT_TEMPORARY: u8 = 3;
+
+// Longest text libosdp will put in a single osdp_TEXT command
+const TEXT_MAX_LEN: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReaderLedColor {
+    Off,
+    Red,
+    Green,
+    Amber,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl From<ReaderLedColor> for OsdpLedColor {
+    fn from(color: ReaderLedColor) -> Self {
+        match color {
+            ReaderLedColor::Off => OsdpLedColor::None,
+            ReaderLedColor::Red => OsdpLedColor::Red,
+            ReaderLedColor::Green => OsdpLedColor::Green,
+            ReaderLedColor::Amber => OsdpLedColor::Amber,
+            ReaderLedColor::Blue => OsdpLedColor::Blue,
+            ReaderLedColor::Magenta => OsdpLedColor::Magenta,
+            ReaderLedColor::Cyan => OsdpLedColor::Cyan,
+        }
+    }
+}
+
+// OSDP times are counted in units of 100ms
+fn tenths(ms: u32) -> u32 {
+    ms.div_ceil(100)
+}
+
+// Translate a reader.* command into the reader id and the OSDP command sent to it
+pub fn reader_command(command: MANAGECommand) -> Result<(i32, OsdpCommand), CommandOutcome> {
+    match command {
+        MANAGECommand::ReaderLed {
+            reader,
+            color,
+            duration_ms,
+        } => {
+            // The same colour for on and off keeps the LED steady
+            let params = OsdpLedParams {
+                on_count: 1,
+                on_color: color.into(),
+                off_color: color.into(),
+                ..Default::default()
+            };
+            let led = if duration_ms == 0 {
+                OsdpCommandLed {
+                    temporary: OsdpLedParams {
+                        control_code: LED_TEMPORARY_CANCEL,
+                        ..Default::default()
+                    },
+                    permanent: OsdpLedParams {
+                        control_code: LED_PERMANENT_SET,
+                        ..params
+                    },
+                    ..Default::default()
+                }
+            } else {
+                // The reader returns to the permanent colour once the timer runs out
+                OsdpCommandLed {
+                    temporary: OsdpLedParams {
+                        control_code: LED_TEMPORARY_SET,
+                        timer_count: tenths(duration_ms).min(u16::MAX as u32) as u16,
+                        ..params
+                    },
+                    ..Default::default()
+                }
+            };
+            Ok((reader, OsdpCommand::Led(led)))
+        }
+        MANAGECommand::ReaderBuzzer {
+            reader,
+            count,
+            on_ms,
+            off_ms,
+        } => Ok((
+            reader,
+            OsdpCommand::Buzzer(OsdpCommandBuzzer {
+                control_code: BUZZER_DEFAULT_TONE,
+                on_count: tenths(on_ms).clamp(1, u8::MAX as u32) as u8,
+                off_count: tenths(off_ms).min(u8::MAX as u32) as u8,
+                rep_count: count,
+                ..Default::default()
+            }),
+        )),
+        MANAGECommand::ReaderText {
+            reader,
+            text,
+            duration_secs,
+        } => {
+            if !text.is_ascii() || text.len() > TEXT_MAX_LEN {
+                return Err(CommandOutcome::Rejected {
+                    reason: format!("Text must be at most {} ASCII characters", TEXT_MAX_LEN),
+                });
+            }
+            Ok((
+                reader,
+                OsdpCommand::Text(OsdpCommandText {
+                    control_code: if duration_secs == 0 {
+                        TEXT_PERMANENT
+                    } else {
+                        TEXT_TEMPORARY
+                    },
+                    temp_time: duration_secs,
+                    offset_row: 1,
+                    offset_col: 1,
+                    data: text.into_bytes(),
+                    ..Default::default()
+                }),
+            ))
+        }
+        _ => Err(CommandOutcome::Rejected {
+            reason: "Not a reader command".to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timed_led_uses_the_temporary_state() {
+        let command = reader_command(MANAGECommand::ReaderLed {
+            reader: 1,
+            color: ReaderLedColor::Green,
+            duration_ms: 3000,
+        });
+        let Ok((1, OsdpCommand::Led(led))) = command else {
+            panic!("not an LED command");
+        };
+        assert_eq!(led.temporary.control_code, LED_TEMPORARY_SET);
+        assert_eq!(led.temporary.on_color, OsdpLedColor::Green);
+        assert_eq!(led.temporary.timer_count, 30);
+        assert_eq!(led.permanent.control_code, 0);
+
+        let command = reader_command(MANAGECommand::ReaderLed {
+            reader: 0,
+            color: ReaderLedColor::Red,
+            duration_ms: 0,
+        });
+        let Ok((0, OsdpCommand::Led(led))) = command else {
+            panic!("not an LED command");
+        };
+        assert_eq!(led.temporary.control_code, LED_TEMPORARY_CANCEL);
+        assert_eq!(led.permanent.control_code, LED_PERMANENT_SET);
+        assert_eq!(led.permanent.on_color, OsdpLedColor::Red);
+    }
+
+    #[test]
+    fn every_led_color_is_mapped() {
+        let colors = [
+            (ReaderLedColor::Off, OsdpLedColor::None, "off"),
+            (ReaderLedColor::Red, OsdpLedColor::Red, "red"),
+            (ReaderLedColor::Green, OsdpLedColor::Green, "green"),
+            (ReaderLedColor::Amber, OsdpLedColor::Amber, "amber"),
+            (ReaderLedColor::Blue, OsdpLedColor::Blue, "blue"),
+            (ReaderLedColor::Magenta, OsdpLedColor::Magenta, "magenta"),
+            (ReaderLedColor::Cyan, OsdpLedColor::Cyan, "cyan"),
+        ];
+        for (color, osdp_color, name) in colors {
+            // A colour missing from the list above doesn't build
+            match color {
+                ReaderLedColor::Off
+                | ReaderLedColor::Red
+                | ReaderLedColor::Green
+                | ReaderLedColor::Amber
+                | ReaderLedColor::Blue
+                | ReaderLedColor::Magenta
+                | ReaderLedColor::Cyan => {}
+            }
+            assert_eq!(OsdpLedColor::from(color), osdp_color);
+            assert_eq!(u8::from(OsdpLedColor::from(color)), u8::from(osdp_color));
+            assert_eq!(
+                serde_json::to_string(&color).unwrap(),
+                format!("\"{}\"", name)
+            );
+        }
+    }
+
+    #[test]
+    fn buzzer_and_text_are_mapped() {
+        let Ok((0, OsdpCommand::Buzzer(buzzer))) = reader_command(MANAGECommand::ReaderBuzzer {
+            reader: 0,
+            count: 2,
+            on_ms: 150,
+            off_ms: 100,
+        }) else {
+            panic!("not a buzzer command");
+        };
+        assert_eq!(buzzer.control_code, BUZZER_DEFAULT_TONE);
+        assert_eq!((buzzer.on_count, buzzer.off_count), (2, 1));
+        assert_eq!(buzzer.rep_count, 2);
+
+        let Ok((0, OsdpCommand::Text(text))) = reader_command(MANAGECommand::ReaderText {
+            reader: 0,
+            text: "Welcome".to_string(),
+            duration_secs: 5,
+        }) else {
+            panic!("not a text command");
+        };
+        assert_eq!(text.control_code, TEXT_TEMPORARY);
+        assert_eq!(text.data, b"Welcome");
+
+        assert!(matches!(
+            reader_command(MANAGECommand::ReaderText {
+                reader: 0,
+                text: "x".repeat(TEXT_MAX_LEN + 1),
+                duration_secs: 0,
+            }),
+            Err(CommandOutcome::Rejected { .. })
+        ));
+    }
+}
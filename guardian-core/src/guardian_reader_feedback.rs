@@ -0,0 +1,151 @@
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::guardian_reader_control::ReaderLedColor;
+use super::manage_command::{CommandRequest, MANAGECommand};
+
+const UNLOCK_BEEP_MS: u32 = 200;
+
+// How the reader reflects the door without MANAGE driving it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReaderFeedbackPolicy {
+    pub enabled: bool,
+    // Shown while the door is secured
+    pub idle_color: ReaderLedColor,
+    // Shown while the door is unlocked or open
+    pub released_color: ReaderLedColor,
+    pub release_beep: bool,
+}
+
+impl Default for ReaderFeedbackPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_color: ReaderLedColor::Red,
+            released_color: ReaderLedColor::Green,
+            release_beep: true,
+        }
+    }
+}
+
+// Turns door state changes into reader commands for the OSDP thread
+pub struct ReaderFeedback {
+    policy: ReaderFeedbackPolicy,
+    // Reader ids of the readers at the door
+    readers: Vec<i32>,
+    reader_command_tx: Option<Sender<CommandRequest>>,
+}
+
+impl ReaderFeedback {
+    pub fn new(
+        policy: ReaderFeedbackPolicy,
+        readers: Vec<i32>,
+        reader_command_tx: Sender<CommandRequest>,
+    ) -> Self {
+        Self {
+            policy,
+            readers,
+            reader_command_tx: Some(reader_command_tx),
+        }
+    }
+
+    // For doors without a reader
+    pub fn none() -> Self {
+        Self {
+            policy: ReaderFeedbackPolicy {
+                enabled: false,
+                ..Default::default()
+            },
+            readers: Vec::new(),
+            reader_command_tx: None,
+        }
+    }
+
+    // The door was released, for the given time or until door_secured() when there is none
+    pub fn door_released(&self, duration: Option<Duration>) {
+        // The reader falls back to the idle colour by itself once a timed release ends
+        self.send(|reader| MANAGECommand::ReaderLed {
+            reader,
+            color: self.policy.released_color,
+            duration_ms: duration.map_or(0, |duration| duration.as_millis() as u32),
+        });
+        if self.policy.release_beep {
+            self.send(|reader| MANAGECommand::ReaderBuzzer {
+                reader,
+                count: 1,
+                on_ms: UNLOCK_BEEP_MS,
+                off_ms: 0,
+            });
+        }
+    }
+
+    pub fn door_secured(&self) {
+        self.send(|reader| MANAGECommand::ReaderLed {
+            reader,
+            color: self.policy.idle_color,
+            duration_ms: 0,
+        });
+    }
+
+    // Send the command to every reader at the door
+    fn send(&self, command: impl Fn(i32) -> MANAGECommand) {
+        if !self.policy.enabled {
+            return;
+        }
+        if let Some(reader_command_tx) = &self.reader_command_tx {
+            for &reader in &self.readers {
+                if reader_command_tx
+                    .send(CommandRequest::local(command(reader)))
+                    .is_err()
+                {
+                    log::error!("OSDP control panel thread is not running!");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn disabled_policy_leaves_the_reader_alone() {
+        let (reader_command_tx, reader_command_rx) = channel();
+        let policy = ReaderFeedbackPolicy {
+            enabled: false,
+            ..Default::default()
+        };
+        let feedback = ReaderFeedback::new(policy, vec![0], reader_command_tx);
+
+        feedback.door_released(Some(Duration::from_secs(5)));
+        feedback.door_secured();
+        assert!(reader_command_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn every_reader_at_the_door_follows_it() {
+        let (reader_command_tx, reader_command_rx) = channel();
+        let feedback = ReaderFeedback::new(
+            ReaderFeedbackPolicy::default(),
+            vec![0, 2],
+            reader_command_tx,
+        );
+
+        feedback.door_secured();
+        let readers: Vec<_> = reader_command_rx
+            .try_iter()
+            .map(|request| match request.command {
+                MANAGECommand::ReaderLed { reader, .. } => reader,
+                command => panic!("unexpected command {:?}", command),
+            })
+            .collect();
+        assert_eq!(readers, [0, 2]);
+    }
+}
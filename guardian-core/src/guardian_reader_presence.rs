@@ -0,0 +1,127 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libosdp::PdId;
+use serde::{Deserialize, Serialize};
+
+use super::manage_command::MANAGEReport;
+
+// What the reader told about itself in its osdp_PDID reply
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReaderIdentity {
+    // IEEE OUI of the vendor as hex
+    pub vendor_code: String,
+    pub model: i32,
+    pub version: i32,
+    pub serial_number: String,
+    pub firmware_version: String,
+}
+
+impl From<PdId> for ReaderIdentity {
+    fn from(pd_id: PdId) -> Self {
+        let (vendor_0, vendor_1, vendor_2) = pd_id.vendor_code;
+        let (major, minor, build) = pd_id.firmware_version;
+        Self {
+            vendor_code: hex::encode([vendor_0, vendor_1, vendor_2]),
+            model: pd_id.model,
+            version: pd_id.version,
+            serial_number: hex::encode(pd_id.serial_number),
+            firmware_version: format!("{}.{}.{}", major, minor, build),
+        }
+    }
+}
+
+fn unix_timestamp(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+// Turns the polled online state of one reader into online/offline reports
+pub struct ReaderPresence {
+    reader: i32,
+    online: bool,
+}
+
+impl ReaderPresence {
+    // Readers start offline, the first poll answered is reported as coming online
+    pub fn new(reader: i32) -> Self {
+        Self {
+            reader,
+            online: false,
+        }
+    }
+
+    // Called on every refresh, identity() is only asked for when the reader came online
+    pub fn update(
+        &mut self,
+        online: bool,
+        identity: impl FnOnce() -> Option<ReaderIdentity>,
+        now: SystemTime,
+    ) -> Option<MANAGEReport> {
+        if online == self.online {
+            return None;
+        }
+        self.online = online;
+
+        let timestamp = unix_timestamp(now);
+        if online {
+            let identity = identity();
+            log::info!("Reader {} online: {:?}", self.reader, identity);
+            Some(MANAGEReport::ReaderOnline {
+                reader: self.reader,
+                timestamp,
+                identity,
+            })
+        } else {
+            log::warn!("Reader {} offline", self.reader);
+            Some(MANAGEReport::ReaderOffline {
+                reader: self.reader,
+                timestamp,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn only_transitions_are_reported() {
+        let mut presence = ReaderPresence::new(2);
+        let now = UNIX_EPOCH + Duration::from_secs(1_750_000_000);
+        let identity = || {
+            Some(ReaderIdentity::from(PdId {
+                version: 1,
+                model: 2,
+                vendor_code: (0x00, 0x0a, 0xff),
+                serial_number: [0xde, 0xad, 0xbe, 0xef],
+                firmware_version: (1, 4, 2),
+            }))
+        };
+
+        assert!(presence.update(false, identity, now).is_none());
+        let Some(MANAGEReport::ReaderOnline {
+            reader: 2,
+            timestamp: 1_750_000_000,
+            identity: Some(identity),
+        }) = presence.update(true, identity, now)
+        else {
+            panic!("reader not reported online");
+        };
+        assert_eq!(identity.vendor_code, "000aff");
+        assert_eq!(identity.serial_number, "deadbeef");
+        assert_eq!(identity.firmware_version, "1.4.2");
+
+        assert!(presence
+            .update(true, || panic!("asked twice"), now)
+            .is_none());
+        assert!(matches!(
+            presence.update(false, || None, now + Duration::from_secs(3)),
+            Some(MANAGEReport::ReaderOffline {
+                reader: 2,
+                timestamp: 1_750_000_003,
+            })
+        ));
+    }
+}
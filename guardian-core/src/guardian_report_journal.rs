@@ -0,0 +1,389 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::guardian_hal::SharedClock;
+use super::guardian_storage::GuardianStorage;
+use super::manage_command::MANAGEReport;
+
+const JOURNAL_RAM_CAPACITY: usize = 32;
+const JOURNAL_SPILL_CAPACITY: usize = 64;
+const JOURNAL_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+const JOURNAL_SEQUENCE_BLOCK: u32 = 64;
+// Every spilled entry has its own slot key, "spill0" to "spill63", so a flush or an
+// acknowledgement only writes the entries it touches
+const JOURNAL_SPILL_SLOT_PREFIX: &str = "spill";
+const JOURNAL_SEQUENCE_KEY: &str = "sequence";
+const JOURNAL_BOOT_KEY: &str = "boot";
+
+#[derive(Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub sequence: u32,
+    pub boot: u32,
+    pub timestamp_ms: u64,
+    pub report: MANAGEReport,
+}
+
+// Bounded journal of reports that could not be delivered to MANAGE.
+// Entries live in RAM and spill to NVS when RAM fills up or has been
+// waiting too long, and are kept until MANAGE acknowledges them.
+pub struct ReportJournal {
+    ram: VecDeque<JournalEntry>,
+    spill: VecDeque<JournalEntry>,
+    // Sequence stored in each spill slot
+    spill_slots: Vec<Option<u32>>,
+    next_sequence: u32,
+    reserved_sequence: u32,
+    boot: u32,
+    replayed_through: Option<u32>,
+    oldest_unflushed: Option<Instant>,
+    storage: GuardianStorage,
+    clock: SharedClock,
+    started: Instant,
+}
+
+impl ReportJournal {
+    pub fn load(mut storage: GuardianStorage, clock: SharedClock) -> Self {
+        // Count boots so timestamps from different boots can be told apart
+        let boot = storage.load::<u32>(JOURNAL_BOOT_KEY).unwrap_or(0) + 1;
+        if !storage.store(JOURNAL_BOOT_KEY, &boot) {
+            log::error!("Failed to store the boot count, timestamps may repeat a boot");
+        }
+
+        // Resume after the last reserved sequence block
+        let next_sequence = storage.load::<u32>(JOURNAL_SEQUENCE_KEY).unwrap_or(1);
+
+        // Collect the spilled entries in sequence order
+        let mut spilled: Vec<(usize, JournalEntry)> = (0..JOURNAL_SPILL_CAPACITY)
+            .filter_map(|slot| Some((slot, storage.load(&spill_slot_key(slot))?)))
+            .collect();
+        spilled.sort_by_key(|(_, entry)| entry.sequence);
+        let mut spill_slots = vec![None; JOURNAL_SPILL_CAPACITY];
+        for (slot, entry) in &spilled {
+            spill_slots[*slot] = Some(entry.sequence);
+        }
+        let spill: VecDeque<JournalEntry> = spilled.into_iter().map(|(_, entry)| entry).collect();
+        log::info!(
+            "Report Journal Loaded (boot {}, {} spilled reports)",
+            boot,
+            spill.len()
+        );
+
+        let mut journal = Self {
+            ram: VecDeque::with_capacity(JOURNAL_RAM_CAPACITY),
+            spill,
+            spill_slots,
+            next_sequence,
+            reserved_sequence: next_sequence,
+            boot,
+            replayed_through: None,
+            oldest_unflushed: None,
+            storage,
+            started: clock.now(),
+            clock,
+        };
+        journal.reserve_sequence_block();
+        journal
+    }
+
+    pub fn len(&self) -> usize {
+        self.spill.len() + self.ram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, report: MANAGEReport) {
+        // Reserve sequence numbers in blocks to limit flash writes
+        if self.next_sequence >= self.reserved_sequence {
+            self.reserve_sequence_block();
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        log::info!("Journaling report #{}: {:?}", sequence, report);
+        self.ram.push_back(JournalEntry {
+            sequence,
+            boot: self.boot,
+            timestamp_ms: self.uptime().as_millis() as u64,
+            report,
+        });
+        self.oldest_unflushed.get_or_insert(self.clock.now());
+
+        // Spill to NVS once RAM is full
+        if self.ram.len() >= JOURNAL_RAM_CAPACITY {
+            self.flush();
+        }
+    }
+
+    // Spill RAM entries to NVS if they have been waiting too long
+    pub fn tick(&mut self) {
+        if let Some(oldest_unflushed) = self.oldest_unflushed {
+            if self.clock.now().saturating_duration_since(oldest_unflushed) > JOURNAL_FLUSH_INTERVAL
+            {
+                self.flush();
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if self.ram.is_empty() {
+            return;
+        }
+
+        // Move RAM entries to the spill, dropping the oldest on overflow
+        self.spill.extend(self.ram.drain(..));
+        let overflow = self.spill.len().saturating_sub(JOURNAL_SPILL_CAPACITY);
+        if overflow > 0 {
+            log::warn!(
+                "WARNING: Report Journal Full! Dropping {} oldest reports.",
+                overflow
+            );
+            let dropped: Vec<u32> = self
+                .spill
+                .drain(..overflow)
+                .map(|entry| entry.sequence)
+                .collect();
+            for sequence in dropped {
+                self.unspill(sequence);
+            }
+        }
+        self.oldest_unflushed = None;
+        self.persist_spill();
+    }
+
+    // Start replaying from the oldest entry, called after every reconnect
+    pub fn restart_replay(&mut self) {
+        self.replayed_through = None;
+    }
+
+    // Next entry not yet replayed on the current connection
+    pub fn next_replay(&self) -> Option<(u32, MANAGEReport)> {
+        self.unreplayed().next().map(|entry| {
+            let replay = MANAGEReport::JournalReplay {
+                sequence: entry.sequence,
+                boot: entry.boot,
+                timestamp_ms: entry.timestamp_ms,
+                report: Box::new(entry.report.clone()),
+            };
+            (entry.sequence, replay)
+        })
+    }
+
+    // Whether an entry is still waiting to be replayed on the current connection
+    pub fn replay_pending(&self) -> bool {
+        self.unreplayed().next().is_some()
+    }
+
+    pub fn mark_replayed(&mut self, sequence: u32) {
+        self.replayed_through = Some(sequence);
+    }
+
+    // MANAGE acknowledges every entry up to and including the sequence
+    pub fn acknowledge(&mut self, sequence: u32) {
+        let acknowledged: Vec<u32> = self
+            .spill
+            .iter()
+            .map(|entry| entry.sequence)
+            .filter(|spilled| *spilled <= sequence)
+            .collect();
+        self.spill.retain(|entry| entry.sequence > sequence);
+        self.ram.retain(|entry| entry.sequence > sequence);
+        if self.ram.is_empty() {
+            self.oldest_unflushed = None;
+        }
+        for sequence in acknowledged {
+            self.unspill(sequence);
+        }
+        log::info!(
+            "Report Journal acknowledged through #{} ({} remaining)",
+            sequence,
+            self.len()
+        );
+    }
+
+    fn unreplayed(&self) -> impl Iterator<Item = &JournalEntry> {
+        self.spill.iter().chain(self.ram.iter()).filter(|entry| {
+            self.replayed_through
+                .map_or(true, |replayed| entry.sequence > replayed)
+        })
+    }
+
+    // Milliseconds since the journal was loaded at boot
+    fn uptime(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.started)
+    }
+
+    fn reserve_sequence_block(&mut self) {
+        self.reserved_sequence = self.next_sequence + JOURNAL_SEQUENCE_BLOCK;
+        if !self
+            .storage
+            .store(JOURNAL_SEQUENCE_KEY, &self.reserved_sequence)
+        {
+            log::error!("Failed to reserve sequence numbers, they may repeat after a restart");
+        }
+    }
+
+    // Store every spilled entry that isn't in a slot yet, retrying earlier failures
+    fn persist_spill(&mut self) {
+        for entry in &self.spill {
+            if self.spill_slots.contains(&Some(entry.sequence)) {
+                continue;
+            }
+            let Some(slot) = self.spill_slots.iter().position(Option::is_none) else {
+                break;
+            };
+            if self.storage.store(&spill_slot_key(slot), entry) {
+                self.spill_slots[slot] = Some(entry.sequence);
+            } else {
+                log::error!(
+                    "Failed to spill report #{}, it is lost on restart",
+                    entry.sequence
+                );
+            }
+        }
+    }
+
+    // Free the slot of an entry that left the spill, a slot that can't be removed is
+    // overwritten by the next entry spilled into it
+    fn unspill(&mut self, sequence: u32) {
+        let Some(slot) = self
+            .spill_slots
+            .iter()
+            .position(|spilled| *spilled == Some(sequence))
+        else {
+            return;
+        };
+        self.spill_slots[slot] = None;
+        if !self.storage.remove(&spill_slot_key(slot)) {
+            log::error!("Failed to remove spilled report #{}", sequence);
+        }
+    }
+}
+
+fn spill_slot_key(slot: usize) -> String {
+    format!("{}{}", JOURNAL_SPILL_SLOT_PREFIX, slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::guardian_hal::fake::ManualClock;
+    use crate::guardian_storage::MemoryStore;
+
+    fn journal(store: &MemoryStore, clock: &ManualClock) -> ReportJournal {
+        ReportJournal::load(GuardianStorage::new(store.clone()), Arc::new(clock.clone()))
+    }
+
+    fn replay_all(journal: &mut ReportJournal) -> Vec<u32> {
+        let mut sequences = Vec::new();
+        while let Some((sequence, _)) = journal.next_replay() {
+            journal.mark_replayed(sequence);
+            sequences.push(sequence);
+        }
+        sequences
+    }
+
+    #[test]
+    fn replays_in_order_until_acknowledged() {
+        let (store, clock) = (MemoryStore::default(), ManualClock::new());
+        let mut journal = journal(&store, &clock);
+        journal.push(MANAGEReport::DoorOpened { door_id: 0 });
+        clock.advance(Duration::from_millis(1500));
+        journal.push(MANAGEReport::DoorClosed { door_id: 0 });
+
+        let Some((
+            _,
+            MANAGEReport::JournalReplay {
+                timestamp_ms,
+                report,
+                ..
+            },
+        )) = journal.next_replay()
+        else {
+            panic!("expected a replay");
+        };
+        assert_eq!(timestamp_ms, 0);
+        assert!(matches!(*report, MANAGEReport::DoorOpened { door_id: 0 }));
+        assert_eq!(replay_all(&mut journal), [1, 2]);
+        assert_eq!(replay_all(&mut journal), [] as [u32; 0]);
+
+        // Unacknowledged entries are replayed again after a reconnect
+        journal.restart_replay();
+        journal.acknowledge(1);
+        assert_eq!(replay_all(&mut journal), [2]);
+        journal.acknowledge(2);
+        assert!(journal.is_empty());
+    }
+
+    #[test]
+    fn spilled_entries_survive_a_reboot() {
+        let (store, clock) = (MemoryStore::default(), ManualClock::new());
+        let mut journal = journal(&store, &clock);
+        journal.push(MANAGEReport::DoorForcedOpen { door_id: 0 });
+        journal.push(MANAGEReport::DoorClosed { door_id: 0 });
+
+        // Entries only reach NVS after waiting for the flush interval
+        journal.tick();
+        clock.advance(JOURNAL_FLUSH_INTERVAL + Duration::from_secs(1));
+        journal.tick();
+
+        let mut rebooted = self::journal(&store, &clock);
+        assert_eq!(rebooted.len(), 2);
+        assert_eq!(replay_all(&mut rebooted), [1, 2]);
+
+        // Sequence numbers keep increasing across boots
+        rebooted.push(MANAGEReport::DoorOpened { door_id: 0 });
+        let Some((sequence, MANAGEReport::JournalReplay { boot, .. })) = rebooted.next_replay()
+        else {
+            panic!("expected a replay");
+        };
+        assert!(sequence > 2);
+        assert_eq!(boot, 2);
+    }
+
+    #[test]
+    fn spilled_entries_are_stored_one_per_slot() {
+        let (store, clock) = (MemoryStore::default(), ManualClock::new());
+        let storage = GuardianStorage::new(store.clone());
+        let mut journal = journal(&store, &clock);
+        journal.push(MANAGEReport::DoorOpened { door_id: 0 });
+        journal.push(MANAGEReport::DoorClosed { door_id: 0 });
+        journal.flush();
+        assert!(storage.load::<JournalEntry>("spill0").is_some());
+        assert!(storage.load::<JournalEntry>("spill1").is_some());
+
+        // Acknowledging only removes the slots of the acknowledged entries
+        journal.acknowledge(1);
+        assert!(storage.load::<JournalEntry>("spill0").is_none());
+        journal.push(MANAGEReport::DoorForcedOpen { door_id: 0 });
+        journal.flush();
+        let Some(entry) = storage.load::<JournalEntry>("spill0") else {
+            panic!("expected the freed slot to be reused");
+        };
+        assert!(matches!(
+            entry.report,
+            MANAGEReport::DoorForcedOpen { door_id: 0 }
+        ));
+
+        let mut rebooted = self::journal(&store, &clock);
+        assert_eq!(replay_all(&mut rebooted), [2, entry.sequence]);
+    }
+
+    #[test]
+    fn overflow_drops_the_oldest_entries() {
+        let (store, clock) = (MemoryStore::default(), ManualClock::new());
+        let mut journal = journal(&store, &clock);
+        for _ in 0..(JOURNAL_SPILL_CAPACITY + JOURNAL_RAM_CAPACITY) {
+            journal.push(MANAGEReport::DoorOpened { door_id: 0 });
+        }
+        assert_eq!(journal.len(), JOURNAL_SPILL_CAPACITY);
+
+        let first = JOURNAL_RAM_CAPACITY as u32 + 1;
+        assert_eq!(replay_all(&mut journal).first(), Some(&first));
+    }
+}
@@ -0,0 +1,336 @@
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use atomic_time::AtomicInstant;
+use libosdp::{Channel, ControlPanel, OsdpEvent, PdInfo, PdInfoBuilder};
+
+use super::aperture_door_security::{DoorSecurity, DoorStatus};
+use super::guardian_config::ReaderConfig;
+use super::guardian_firmware_update::FirmwareUpdater;
+use super::guardian_global_status::{DOORS_TICKING, MANAGE_CONNECTED, PD_ONLINE};
+use super::guardian_hal::{FirmwareSlots, InputPin, OutputPin, SystemMetrics, SystemWatchdog};
+use super::guardian_health::{doors_ticking, firmware_healthy, is_healthy};
+use super::guardian_osdp_events::OsdpEventHandler;
+use super::guardian_osdp_secure_channel::SecureChannel;
+use super::guardian_reader_control::reader_command;
+use super::guardian_reader_presence::ReaderPresence;
+use super::guardian_telemetry::TelemetrySources;
+use super::guardian_watchdog::Watchdog;
+use super::manage_command::{CommandOutcome, CommandRequest, MANAGECommand, MANAGEReport};
+
+// Threads shared by the firmware and the simulator
+pub const OSDP_REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+pub const DOOR_SECURITY_LOOP_INTERVAL: Duration = Duration::from_millis(100);
+pub const SYSTEM_HEALTH_LOOP_INTERVAL: Duration = Duration::from_secs(5);
+pub const FIRMWARE_RESTART_DELAY: Duration = Duration::from_secs(3);
+pub const WATCHDOG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Describe the configured readers on the shared bus, channel() opens the bus for each
+pub fn reader_pd_infos(
+    readers: &[ReaderConfig],
+    baud_rate: u32,
+    secure_channels: &[SecureChannel],
+    channel: impl Fn() -> Box<dyn Channel>,
+) -> Vec<PdInfo> {
+    readers
+        .iter()
+        .zip(secure_channels)
+        .map(|(reader, secure_channel)| {
+            secure_channel
+                .pd_info(PdInfoBuilder::new())
+                .name(&reader.name)
+                .unwrap()
+                .address(reader.address)
+                .unwrap()
+                .baud_rate(baud_rate as i32)
+                .unwrap()
+                .channel(channel())
+                .build()
+        })
+        .collect()
+}
+
+// Create thread to handle OSDP CP events & other tasks
+pub fn spawn_osdp_control_panel(
+    mut cp: ControlPanel,
+    mut event_handler: OsdpEventHandler,
+    mut secure_channels: Vec<SecureChannel>,
+    reader_command_rx: Receiver<CommandRequest>,
+    report_tx: Sender<MANAGEReport>,
+    last_tick: Arc<AtomicInstant>,
+) -> JoinHandle<()> {
+    // Initialize a channel for processing events
+    let (event_tx, event_rx) = channel::<(i32, OsdpEvent)>();
+
+    // Setup Event Handler
+    cp.set_event_callback(move |pd, event| {
+        // Send Event to Event Handler
+        event_tx.send((pd, event)).expect("Failed to send event");
+
+        // Report Back Successful Event Handling
+        0
+    });
+    log::info!("OSDP Event Handler Initialized");
+
+    let mut reader_presences: Vec<ReaderPresence> = (0..secure_channels.len() as i32)
+        .map(ReaderPresence::new)
+        .collect();
+
+    // Initialize Loop Timer
+    let mut next_refresh = Instant::now() + OSDP_REFRESH_INTERVAL;
+
+    thread::spawn(move || {
+        // Loop and wait for events
+        loop {
+            // Refresh Control Panel state
+            cp.refresh();
+
+            // Check for events, only trusting a reader over the secure channel
+            while let Ok((pd, event)) = event_rx.try_recv() {
+                let Some(secure_channel) = secure_channels.get(pd as usize) else {
+                    continue;
+                };
+                if secure_channel.required() && !cp.is_sc_active(pd) {
+                    log::warn!(
+                        "Ignoring OSDP event from reader {} without secure channel",
+                        pd
+                    );
+                    continue;
+                }
+                event_handler.handle_event(pd, event);
+            }
+            event_handler.poll(Instant::now());
+
+            // Send LED, buzzer and text commands to the readers
+            while let Ok(request) = reader_command_rx.try_recv() {
+                let result = send_reader_command(&mut cp, secure_channels.len(), request.command);
+                if let Some(request_id) = request.request_id {
+                    report_tx
+                        .send(MANAGEReport::CommandResult { request_id, result })
+                        .unwrap();
+                }
+            }
+
+            for (pd, secure_channel) in secure_channels.iter_mut().enumerate() {
+                let pd = pd as i32;
+
+                // Watch the secure channel and rotate a new reader off the default key
+                let online = cp.is_online(pd);
+                if let Some(keyset) =
+                    secure_channel.poll(online, cp.is_sc_active(pd), Instant::now())
+                {
+                    if let Err(error) = cp.send_command(pd, keyset) {
+                        log::error!("Failed to send KEYSET to reader {}: {:?}", pd, error);
+                        secure_channel.keyset_failed();
+                    }
+                }
+
+                // Report the reader coming online or going offline
+                let identity = || cp.get_pd_id(pd).ok().map(Into::into);
+                if let Some(report) =
+                    reader_presences[pd as usize].update(online, identity, SystemTime::now())
+                {
+                    report_tx.send(report).unwrap();
+                }
+                PD_ONLINE[pd as usize].store(online, Ordering::SeqCst);
+            }
+            last_tick.store(Instant::now(), Ordering::SeqCst);
+
+            // Sleep for ~50ms
+            thread::sleep(next_refresh.saturating_duration_since(Instant::now()));
+
+            // Update next refresh time
+            next_refresh += OSDP_REFRESH_INTERVAL;
+        }
+    })
+}
+
+// Create thread to handle door system
+pub fn spawn_door_security<O, I>(
+    mut door_security: DoorSecurity<O, I>,
+    command_rx: Receiver<CommandRequest>,
+    last_tick: Arc<AtomicInstant>,
+    status: Arc<Mutex<DoorStatus>>,
+) -> JoinHandle<()>
+where
+    O: OutputPin + Send + 'static,
+    I: InputPin + Send + 'static,
+{
+    thread::spawn(move || {
+        loop {
+            match command_rx.try_recv() {
+                Ok(request) => {
+                    // We received a command, handle it and report the result
+                    door_security.handle_request(request);
+                }
+                Err(_) => {
+                    // Tick the door security system and sleep for a while
+                    door_security.tick();
+                    *status.lock().unwrap() = door_security.status();
+                    last_tick.store(Instant::now(), Ordering::SeqCst);
+                    thread::sleep(DOOR_SECURITY_LOOP_INTERVAL);
+                }
+            }
+        }
+    })
+}
+
+// Send a reader.* command to the reader it names
+fn send_reader_command(
+    cp: &mut ControlPanel,
+    readers: usize,
+    command: MANAGECommand,
+) -> CommandOutcome {
+    let (reader, command) = match reader_command(command) {
+        Ok(command) => command,
+        Err(outcome) => return outcome,
+    };
+    if reader < 0 || reader as usize >= readers {
+        return CommandOutcome::Rejected {
+            reason: format!("Unknown reader {}", reader),
+        };
+    }
+    if !cp.is_online(reader) {
+        return CommandOutcome::HardwareError {
+            error: format!("Reader {} offline", reader),
+        };
+    }
+    match cp.send_command(reader, command) {
+        Ok(()) => CommandOutcome::Success,
+        Err(error) => {
+            log::error!("Failed to send reader command: {:?}", error);
+            CommandOutcome::HardwareError {
+                error: format!("Failed to send reader command: {:?}", error),
+            }
+        }
+    }
+}
+
+// Create thread to handle firmware updates and confirm a new image once healthy
+pub fn spawn_firmware_update<F>(
+    mut firmware_updater: FirmwareUpdater<F>,
+    command_rx: Receiver<CommandRequest>,
+    report_tx: Sender<MANAGEReport>,
+    last_tick: Arc<AtomicInstant>,
+) -> JoinHandle<()>
+where
+    F: FirmwareSlots + Send + 'static,
+{
+    thread::spawn(move || loop {
+        last_tick.store(Instant::now(), Ordering::SeqCst);
+        match command_rx.recv_timeout(SYSTEM_HEALTH_LOOP_INTERVAL) {
+            Ok(CommandRequest {
+                request_id,
+                command:
+                    MANAGECommand::FirmwareUpdate {
+                        url,
+                        sha256,
+                        signature,
+                    },
+            }) => {
+                let result = firmware_updater.install(&url, &sha256, &signature, &last_tick);
+                let installed = result == CommandOutcome::Success;
+                if let Some(request_id) = request_id {
+                    report_tx
+                        .send(MANAGEReport::CommandResult { request_id, result })
+                        .unwrap();
+                }
+                if installed {
+                    // Give the result a moment to reach MANAGE before restarting
+                    thread::sleep(FIRMWARE_RESTART_DELAY);
+                    firmware_updater.restart();
+                }
+            }
+            Ok(request) => log::warn!("Ignoring unexpected command: {:?}", request.command),
+            Err(RecvTimeoutError::Timeout) => {
+                let healthy = firmware_healthy(
+                    DOORS_TICKING.load(Ordering::SeqCst),
+                    MANAGE_CONNECTED.load(Ordering::SeqCst),
+                );
+                firmware_updater.check_health(healthy, Instant::now());
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    })
+}
+
+// Create thread to supervise the worker threads
+pub fn spawn_watchdog<W>(mut watchdog: Watchdog<W>) -> JoinHandle<()>
+where
+    W: SystemWatchdog + Send + 'static,
+{
+    thread::spawn(move || {
+        watchdog.subscribe();
+        loop {
+            watchdog.check(Instant::now());
+            thread::sleep(WATCHDOG_CHECK_INTERVAL);
+        }
+    })
+}
+
+// Create thread to handle system health
+pub fn spawn_system_health<M>(
+    last_ticks: Vec<Arc<AtomicInstant>>,
+    report_tx: Sender<MANAGEReport>,
+    heartbeat_interval: Duration,
+    readers: usize,
+    metrics: M,
+    telemetry: TelemetrySources,
+    last_tick: Arc<AtomicInstant>,
+) -> JoinHandle<()>
+where
+    M: SystemMetrics + Send + 'static,
+{
+    // Initialize Heartbeat
+    let mut next_heartbeat = Instant::now();
+
+    thread::spawn(move || {
+        loop {
+            // Sleep for a while
+            thread::sleep(SYSTEM_HEALTH_LOOP_INTERVAL);
+
+            // Retrieve elapsed time of the door that ticked longest ago
+            let elapsed = last_ticks
+                .iter()
+                .map(|last_tick| last_tick.load(Ordering::SeqCst).elapsed())
+                .max()
+                .unwrap_or_default();
+
+            // Online status of each configured reader
+            let readers_online: Vec<bool> = PD_ONLINE[..readers]
+                .iter()
+                .map(|online| online.load(Ordering::SeqCst))
+                .collect();
+
+            // Display System Status
+            let network = metrics.network();
+            let status = format!(
+                "GUARDIAN SYSTEM STATUS\n---\nOSDP Online: {:?}\nLink Up: {}\nIP: {}\nLast Door Tick: {} seconds\n---",
+                readers_online,
+                network.link_up,
+                network.ip.as_deref().unwrap_or("Not Available!"),
+                elapsed.as_secs(),
+            );
+            log::info!("{}", status);
+            let healthy = is_healthy(elapsed, !readers_online.contains(&false));
+            DOORS_TICKING.store(doors_ticking(elapsed), Ordering::SeqCst);
+
+            // Prepare Heartbeat
+            let now = Instant::now();
+            if next_heartbeat < now {
+                next_heartbeat = now + heartbeat_interval;
+                let heartbeat = MANAGEReport::Heartbeat {
+                    is_healthy: healthy,
+                };
+                report_tx.send(heartbeat).unwrap();
+                report_tx
+                    .send(telemetry.report(&metrics, readers_online, now))
+                    .unwrap();
+            }
+            last_tick.store(now, Ordering::SeqCst);
+        }
+    })
+}
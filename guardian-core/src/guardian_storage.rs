@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Raw blob storage backing a single namespace (NVS on the device)
+pub trait KeyValueStore {
+    type Error: Debug;
+
+    fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error>;
+    fn set_blob(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Error>;
+    fn remove_blob(&mut self, key: &str) -> Result<(), Self::Error>;
+}
+
+// Object safe view of a KeyValueStore with the error flattened for logging
+trait BlobStore: Send {
+    fn load_blob(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+    fn store_blob(&mut self, key: &str, data: &[u8]) -> Result<(), String>;
+    fn erase_blob(&mut self, key: &str) -> Result<(), String>;
+}
+
+impl<T: KeyValueStore + Send> BlobStore for T {
+    fn load_blob(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        self.get_blob(key).map_err(|error| format!("{:?}", error))
+    }
+
+    fn store_blob(&mut self, key: &str, data: &[u8]) -> Result<(), String> {
+        self.set_blob(key, data)
+            .map_err(|error| format!("{:?}", error))
+    }
+
+    fn erase_blob(&mut self, key: &str) -> Result<(), String> {
+        self.remove_blob(key)
+            .map_err(|error| format!("{:?}", error))
+    }
+}
+
+// Persistent JSON storage on top of a KeyValueStore
+pub struct GuardianStorage {
+    store: Box<dyn BlobStore>,
+}
+
+impl GuardianStorage {
+    pub fn new(store: impl KeyValueStore + Send + 'static) -> Self {
+        Self {
+            store: Box::new(store),
+        }
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        match self.store.load_blob(key) {
+            Ok(Some(data)) => match serde_json::from_slice(&data) {
+                Ok(value) => Some(value),
+                Err(error) => {
+                    log::error!("Failed to decode stored blob {}: {:?}", key, error);
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(error) => {
+                log::error!("Failed to read stored blob {}: {}", key, error);
+                None
+            }
+        }
+    }
+
+    // Raw blob written outside of Guardian, e.g. by factory provisioning
+    pub fn load_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        match self.store.load_blob(key) {
+            Ok(data) => data,
+            Err(error) => {
+                log::error!("Failed to read stored blob {}: {}", key, error);
+                None
+            }
+        }
+    }
+
+    pub fn store_bytes(&mut self, key: &str, data: &[u8]) -> bool {
+        match self.store.store_blob(key, data) {
+            Ok(_) => true,
+            Err(error) => {
+                log::error!("Failed to write stored blob {}: {}", key, error);
+                false
+            }
+        }
+    }
+
+    pub fn store<T: Serialize>(&mut self, key: &str, value: &T) -> bool {
+        let data = serde_json::to_vec(value).unwrap();
+        self.store_bytes(key, &data)
+    }
+
+    // Removing a key that was never stored succeeds
+    pub fn remove(&mut self, key: &str) -> bool {
+        match self.store.erase_blob(key) {
+            Ok(_) => true,
+            Err(error) => {
+                log::error!("Failed to remove stored blob {}: {}", key, error);
+                false
+            }
+        }
+    }
+}
+
+// In-memory store, clones share the same contents so a "reboot" can be simulated
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    blobs: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl KeyValueStore for MemoryStore {
+    type Error = ();
+
+    fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, ()> {
+        Ok(self.blobs.lock().unwrap().get(key).cloned())
+    }
+
+    fn set_blob(&mut self, key: &str, data: &[u8]) -> Result<(), ()> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn remove_blob(&mut self, key: &str) -> Result<(), ()> {
+        self.blobs.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_loads_json() {
+        let mut storage = GuardianStorage::new(MemoryStore::default());
+        assert_eq!(storage.load::<u32>("missing"), None);
+        assert!(storage.store("answer", &42u32));
+        assert_eq!(storage.load::<u32>("answer"), Some(42));
+        assert!(storage.remove("answer"));
+        assert_eq!(storage.load::<u32>("answer"), None);
+    }
+
+    #[test]
+    fn undecodable_blob_loads_as_none() {
+        let mut store = MemoryStore::default();
+        store.set_blob("broken", b"not json").unwrap();
+        let storage = GuardianStorage::new(store);
+        assert_eq!(storage.load::<u32>("broken"), None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::atomic::Ordering;
+use std::sync::mpmc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use atomic_time::AtomicInstant;
+use serde::{Deserialize, Serialize};
+
+use super::aperture_door_security::DoorStatus;
+use super::guardian_global_status::{MANAGE_RECONNECTS, OSDP_RX_QUEUE_FULL};
+use super::guardian_hal::SystemMetrics;
+use super::manage_command::MANAGEReport;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkInfo {
+    pub link_up: bool,
+    // None until DHCP assigned an address
+    pub ip: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WorkerTelemetry {
+    pub name: String,
+    pub last_tick_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QueueTelemetry {
+    pub name: String,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+// What the health thread reads for the telemetry report, besides the platform metrics
+pub struct TelemetrySources {
+    // Worker threads supervised by the watchdog
+    pub workers: Vec<(String, Arc<AtomicInstant>)>,
+    // Updated by each door thread on every tick, indexed by door id
+    pub doors: Vec<Arc<Mutex<DoorStatus>>>,
+    // OSDP serial queues, only looked at
+    pub osdp_queues: Vec<(String, Receiver<u8>)>,
+}
+
+impl TelemetrySources {
+    pub fn report(
+        &self,
+        metrics: &impl SystemMetrics,
+        readers_online: Vec<bool>,
+        now: Instant,
+    ) -> MANAGEReport {
+        let workers = self
+            .workers
+            .iter()
+            .map(|(name, last_tick)| WorkerTelemetry {
+                name: name.clone(),
+                last_tick_ms: now
+                    .saturating_duration_since(last_tick.load(Ordering::SeqCst))
+                    .as_millis() as u64,
+            })
+            .collect();
+        let osdp_queues = self
+            .osdp_queues
+            .iter()
+            .map(|(name, queue)| QueueTelemetry {
+                name: name.clone(),
+                len: queue.len(),
+                capacity: queue.capacity().unwrap_or_default(),
+            })
+            .collect();
+        MANAGEReport::Telemetry {
+            workers,
+            free_heap: metrics.free_heap(),
+            min_free_heap: metrics.min_free_heap(),
+            osdp_queues,
+            osdp_rx_queue_full: OSDP_RX_QUEUE_FULL.load(Ordering::SeqCst),
+            manage_reconnects: MANAGE_RECONNECTS.load(Ordering::SeqCst),
+            network: metrics.network(),
+            readers_online,
+            doors: self
+                .doors
+                .iter()
+                .map(|door| door.lock().unwrap().clone())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpmc::sync_channel;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::guardian_hal::fake::FakeSystemMetrics;
+
+    #[test]
+    fn telemetry_collects_every_source() {
+        // atomic-time only encodes instants from its first use on correctly
+        let osdp_tick = Arc::new(AtomicInstant::now());
+        let now = osdp_tick.load(Ordering::SeqCst) + Duration::from_millis(50);
+        let door = Arc::new(Mutex::new(DoorStatus {
+            door_id: 0,
+            open: Some(false),
+            unlocked: false,
+            motorized_state: None,
+        }));
+        let (rx_sender, rx_receiver) = sync_channel::<u8>(4);
+        rx_sender.send(0x53).unwrap();
+        let sources = TelemetrySources {
+            workers: vec![("osdp".to_string(), osdp_tick)],
+            doors: vec![door],
+            osdp_queues: vec![("rx".to_string(), rx_receiver)],
+        };
+
+        let MANAGEReport::Telemetry {
+            workers,
+            free_heap,
+            osdp_queues,
+            network,
+            readers_online,
+            doors,
+            ..
+        } = sources.report(&FakeSystemMetrics, vec![true, false], now)
+        else {
+            panic!("no telemetry");
+        };
+        assert_eq!(workers[0].last_tick_ms, 50);
+        assert_eq!(free_heap, 120_000);
+        assert_eq!(
+            osdp_queues,
+            [QueueTelemetry {
+                name: "rx".to_string(),
+                len: 1,
+                capacity: 4,
+            }]
+        );
+        assert!(network.link_up);
+        assert_eq!(readers_online, [true, false]);
+        assert_eq!(doors[0].open, Some(false));
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use atomic_time::AtomicInstant;
+
+use super::guardian_hal::SystemWatchdog;
+use super::guardian_storage::GuardianStorage;
+use super::manage_command::MANAGEReport;
+
+// Worker that stalled last, kept in the "watchdog" NVS namespace until reported after the restart
+const STALLED_WORKER_KEY: &str = "stalled_worker";
+
+// A worker thread that hasn't ticked for this long is considered wedged
+pub const WORKER_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Worker {
+    name: String,
+    last_tick: Arc<AtomicInstant>,
+}
+
+// Supervises the worker threads, feeding the chip's task watchdog while all of them tick
+// and restarting the controller once one of them is wedged
+pub struct Watchdog<W: SystemWatchdog> {
+    system: W,
+    storage: GuardianStorage,
+    workers: Vec<Worker>,
+}
+
+impl<W: SystemWatchdog> Watchdog<W> {
+    // Reports why the controller restarted, naming the worker if the supervisor restarted it
+    pub fn new(system: W, storage: GuardianStorage, report_tx: Sender<MANAGEReport>) -> Self {
+        let mut watchdog = Self {
+            system,
+            storage,
+            workers: Vec::new(),
+        };
+
+        let reset_reason = watchdog.system.reset_reason();
+        let stalled_worker = watchdog
+            .storage
+            .load::<Option<String>>(STALLED_WORKER_KEY)
+            .flatten();
+        if stalled_worker.is_some() {
+            watchdog.set_stalled_worker(None);
+        }
+        log::info!(
+            "Reset reason: {}, stalled worker: {:?}",
+            reset_reason,
+            stalled_worker
+        );
+        report_tx
+            .send(MANAGEReport::SystemReset {
+                reset_reason,
+                stalled_worker,
+            })
+            .unwrap();
+        watchdog
+    }
+
+    // The worker stores the current time in the returned tick on every iteration
+    pub fn register(&mut self, name: &str) -> Arc<AtomicInstant> {
+        let last_tick = Arc::new(AtomicInstant::now());
+        self.workers.push(Worker {
+            name: name.to_string(),
+            last_tick: last_tick.clone(),
+        });
+        last_tick
+    }
+
+    // Every registered worker with its tick, for the telemetry report
+    pub fn worker_ticks(&self) -> Vec<(String, Arc<AtomicInstant>)> {
+        self.workers
+            .iter()
+            .map(|worker| (worker.name.clone(), worker.last_tick.clone()))
+            .collect()
+    }
+
+    // Called from the supervisor thread before the first check
+    pub fn subscribe(&mut self) {
+        self.system.subscribe();
+    }
+
+    // Called by the supervisor thread once a second
+    pub fn check(&mut self, now: Instant) {
+        let stalled = self.workers.iter().find(|worker| {
+            now.saturating_duration_since(worker.last_tick.load(Ordering::SeqCst))
+                >= WORKER_STALL_TIMEOUT
+        });
+        let Some(stalled) = stalled else {
+            self.system.feed();
+            return;
+        };
+
+        // A wedged thread can't be stopped on its own, so the whole controller restarts
+        let name = stalled.name.clone();
+        log::error!("Worker {} stalled, restarting", name);
+        self.set_stalled_worker(Some(name));
+        self.system.reboot();
+    }
+
+    fn set_stalled_worker(&mut self, name: Option<String>) {
+        if !self.storage.store(STALLED_WORKER_KEY, &name) {
+            log::error!("Failed to store the stalled worker");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+    use crate::guardian_hal::fake::FakeSystemWatchdog;
+    use crate::guardian_storage::MemoryStore;
+
+    fn load_watchdog(
+        system: FakeSystemWatchdog,
+        store: &MemoryStore,
+    ) -> (Watchdog<FakeSystemWatchdog>, Receiver<MANAGEReport>) {
+        let (report_tx, report_rx) = channel();
+        let watchdog = Watchdog::new(system, GuardianStorage::new(store.clone()), report_tx);
+        (watchdog, report_rx)
+    }
+
+    #[test]
+    fn fed_while_every_worker_ticks() {
+        let system = FakeSystemWatchdog::new("power_on");
+        let (mut watchdog, report_rx) = load_watchdog(system.clone(), &MemoryStore::default());
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::SystemReset {
+                stalled_worker: None,
+                ..
+            })
+        ));
+
+        let door = watchdog.register("door 0");
+        let osdp = watchdog.register("osdp");
+        let now = Instant::now() + WORKER_STALL_TIMEOUT;
+        door.store(now, Ordering::SeqCst);
+        osdp.store(now, Ordering::SeqCst);
+        watchdog.check(now);
+        assert_eq!(system.feeds(), 1);
+        assert!(!system.rebooted());
+    }
+
+    #[test]
+    fn stalled_worker_restarts_and_is_reported() {
+        let store = MemoryStore::default();
+        let system = FakeSystemWatchdog::new("power_on");
+        let (mut watchdog, _report_rx) = load_watchdog(system.clone(), &store);
+        let door = watchdog.register("door 0");
+        watchdog.register("osdp");
+
+        let now = Instant::now() + WORKER_STALL_TIMEOUT;
+        door.store(now, Ordering::SeqCst);
+        watchdog.check(now);
+        assert_eq!(system.feeds(), 0);
+        assert!(system.rebooted());
+
+        // The next boot names the worker once
+        let (_watchdog, report_rx) = load_watchdog(FakeSystemWatchdog::new("software"), &store);
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::SystemReset {
+                reset_reason,
+                stalled_worker: Some(worker),
+            }) if reset_reason == "software" && worker == "osdp"
+        ));
+        let (_watchdog, report_rx) = load_watchdog(FakeSystemWatchdog::new("power_on"), &store);
+        assert!(matches!(
+            report_rx.try_recv(),
+            Ok(MANAGEReport::SystemReset {
+                stalled_worker: None,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,39 @@
+#![feature(mpmc_channel)]
+#![feature(deadline_api)]
+
+//! Hardware independent Guardian logic.
+//!
+//! Everything in here only talks to the outside world through the traits in
+//! [`guardian_hal`], so it builds and is tested on the host. The ESP firmware
+//! provides the implementations for the real hardware.
+
+pub mod aperture_door_security;
+pub mod guardian_access_cache;
+pub mod guardian_card_format;
+pub mod guardian_command_router;
+pub mod guardian_config;
+pub mod guardian_device_identity;
+pub mod guardian_firmware_update;
+pub mod guardian_global_status;
+pub mod guardian_hal;
+pub mod guardian_health;
+pub mod guardian_hello;
+pub mod guardian_manage_connection;
+pub mod guardian_manage_link;
+pub mod guardian_osdp_events;
+pub mod guardian_osdp_secure_channel;
+pub mod guardian_pin_entry;
+pub mod guardian_reader_control;
+pub mod guardian_reader_feedback;
+pub mod guardian_reader_presence;
+pub mod guardian_report_journal;
+pub mod guardian_runtime;
+pub mod guardian_storage;
+pub mod guardian_telemetry;
+pub mod guardian_watchdog;
+pub mod manage_command;
+pub mod osdp_serial_channel;
+pub mod osdp_time_patch;
+
+#[macro_use]
+extern crate lazy_static;
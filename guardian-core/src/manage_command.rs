@@ -0,0 +1,551 @@
+use libosdp::{OsdpEventCardRead, OsdpEventMfgReply};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::aperture_door_security::{DoorStatus, MotorizedDoorState};
+use super::guardian_card_format::DecodedCard;
+use super::guardian_config::{DoorKind, GuardianConfig, ReaderConfig};
+use super::guardian_osdp_events::ReaderStatusType;
+use super::guardian_reader_control::ReaderLedColor;
+use super::guardian_reader_presence::ReaderIdentity;
+use super::guardian_telemetry::{NetworkInfo, QueueTelemetry, WorkerTelemetry};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "command")]
+pub enum MANAGECommand {
+    // Door commands go to door 0 unless a door id is given
+    #[serde(rename = "door.open")]
+    DoorOpen {
+        #[serde(default)]
+        door_id: u32,
+    },
+    #[serde(rename = "door.close")]
+    DoorClose {
+        #[serde(default)]
+        door_id: u32,
+    },
+    #[serde(rename = "door.stop")]
+    DoorStop {
+        #[serde(default)]
+        door_id: u32,
+    },
+    #[serde(rename = "door.unlock")]
+    DoorUnlock {
+        #[serde(default)]
+        door_id: u32,
+        duration: u32,
+    },
+    #[serde(rename = "access_cache.set")]
+    AccessCacheSet {
+        credentials: Vec<String>,
+        unlock_duration: u32,
+    },
+    #[serde(rename = "access_cache.add")]
+    AccessCacheAdd { credential: String },
+    #[serde(rename = "access_cache.remove")]
+    AccessCacheRemove { credential: String },
+    #[serde(rename = "access_cache.clear")]
+    AccessCacheClear,
+    #[serde(rename = "journal.ack")]
+    JournalAck { sequence: u32 },
+    // Fields present in config are changed, the rest are kept
+    #[serde(rename = "config.set")]
+    ConfigSet { config: Value },
+    #[serde(rename = "config.get")]
+    ConfigGet,
+    // Reader commands go to reader 0 unless a reader id is given
+    // Show the colour for duration_ms, or until told otherwise when it is 0
+    #[serde(rename = "reader.led")]
+    ReaderLed {
+        #[serde(default)]
+        reader: i32,
+        color: ReaderLedColor,
+        #[serde(default)]
+        duration_ms: u32,
+    },
+    #[serde(rename = "reader.buzzer")]
+    ReaderBuzzer {
+        #[serde(default)]
+        reader: i32,
+        count: u8,
+        on_ms: u32,
+        off_ms: u32,
+    },
+    // Show the text for duration_secs, or until told otherwise when it is 0
+    #[serde(rename = "reader.text")]
+    ReaderText {
+        #[serde(default)]
+        reader: i32,
+        text: String,
+        #[serde(default)]
+        duration_secs: u8,
+    },
+    // Install the image at url over HTTPS and restart into it, signature being MANAGE's
+    // firmware signature over sha256 for this controller
+    #[serde(rename = "firmware.update")]
+    FirmwareUpdate {
+        url: String,
+        sha256: String,
+        signature: String,
+    },
+    #[serde(rename = "auth.challenge")]
+    AuthChallenge { nonce: String },
+    #[serde(rename = "auth.accepted")]
+    AuthAccepted { signature: String },
+    // Any other command, wrapped in a CommandPayload signed with the session keys
+    #[serde(rename = "signed")]
+    Signed { payload: String, signature: String },
+}
+
+// Every command name MANAGECommand accepts, announced in the hello report
+pub const SUPPORTED_COMMANDS: [&str; 18] = [
+    "door.open",
+    "door.close",
+    "door.stop",
+    "door.unlock",
+    "access_cache.set",
+    "access_cache.add",
+    "access_cache.remove",
+    "access_cache.clear",
+    "journal.ack",
+    "config.set",
+    "config.get",
+    "reader.led",
+    "reader.buzzer",
+    "reader.text",
+    "firmware.update",
+    "auth.challenge",
+    "auth.accepted",
+    "signed",
+];
+
+// Signed body of a command, the counter must increase for every command in a session
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommandPayload {
+    // Chosen by MANAGE, echoed back in the command.result report
+    pub request_id: String,
+    pub counter: u64,
+    // Seconds since the Unix epoch on MANAGE
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub command: MANAGECommand,
+}
+
+impl MANAGECommand {
+    // Commands exchanged while the session is being authenticated
+    pub fn is_handshake(&self) -> bool {
+        matches!(
+            self,
+            MANAGECommand::AuthChallenge { .. } | MANAGECommand::AuthAccepted { .. }
+        )
+    }
+}
+
+// A command on its way to the subsystem that owns it
+#[derive(Debug)]
+pub struct CommandRequest {
+    // Only commands sent by MANAGE have a result reported back
+    pub request_id: Option<String>,
+    pub command: MANAGECommand,
+}
+
+impl CommandRequest {
+    // Command raised on the controller itself, e.g. an offline access decision
+    pub fn local(command: MANAGECommand) -> Self {
+        Self {
+            request_id: None,
+            command,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CommandOutcome {
+    Success,
+    // The command was understood but not carried out
+    Rejected { reason: String },
+    // Carrying out the command failed, e.g. a relay output could not be driven
+    HardwareError { error: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "command")]
+pub enum MANAGEReport {
+    // Sent first on every connect, doors and readers being indexed by their ids
+    #[serde(rename = "hello")]
+    Hello {
+        firmware_version: String,
+        git_hash: String,
+        // None in the simulator
+        idf_version: Option<String>,
+        reset_reason: String,
+        uptime_secs: u64,
+        mac_address: String,
+        doors: Vec<DoorKind>,
+        readers: Vec<ReaderConfig>,
+        commands: Vec<String>,
+    },
+    #[serde(rename = "heartbeat")]
+    Heartbeat { is_healthy: bool },
+    // Sent along with every heartbeat, readers and doors being indexed by their ids
+    #[serde(rename = "system.telemetry")]
+    Telemetry {
+        workers: Vec<WorkerTelemetry>,
+        free_heap: u32,
+        min_free_heap: u32,
+        osdp_queues: Vec<QueueTelemetry>,
+        osdp_rx_queue_full: u32,
+        manage_reconnects: u32,
+        network: NetworkInfo,
+        readers_online: Vec<bool>,
+        doors: Vec<DoorStatus>,
+    },
+    #[serde(rename = "osdp.card_read")]
+    OsdpCardRead {
+        reader: i32,
+        event: OsdpEventCardRead,
+        decoded: Option<DecodedCard>,
+    },
+    // A PIN typed on the keypad, with the card read before it on a card+PIN reader
+    #[serde(rename = "osdp.pin_entered")]
+    OsdpPinEntered {
+        reader: i32,
+        pin: String,
+        card: Option<OsdpEventCardRead>,
+        decoded: Option<DecodedCard>,
+    },
+    #[serde(rename = "osdp.mfg_reply")]
+    OsdpMfgReply {
+        reader: i32,
+        event: OsdpEventMfgReply,
+    },
+    // Input, output or remote reader tamper states, one entry each
+    #[serde(rename = "osdp.status")]
+    OsdpStatus {
+        reader: i32,
+        status_type: ReaderStatusType,
+        entries: Vec<bool>,
+    },
+    // The reader's own tamper switch and power supply
+    #[serde(rename = "osdp.local_status")]
+    OsdpLocalStatus {
+        reader: i32,
+        tamper: bool,
+        power_failure: bool,
+    },
+    // Sent as soon as a reader starts or stops answering polls
+    #[serde(rename = "reader.online")]
+    ReaderOnline {
+        reader: i32,
+        timestamp: u64,
+        identity: Option<ReaderIdentity>,
+    },
+    #[serde(rename = "reader.offline")]
+    ReaderOffline { reader: i32, timestamp: u64 },
+    #[serde(rename = "osdp.secure_channel_installed")]
+    OsdpSecureChannelInstalled { reader: i32 },
+    #[serde(rename = "osdp.secure_channel_failed")]
+    OsdpSecureChannelFailed { reader: i32, install_mode: bool },
+    #[serde(rename = "access_cache.offline_decision")]
+    OfflineAccessDecision {
+        reader: i32,
+        event: OsdpEventCardRead,
+        decoded: Option<DecodedCard>,
+        granted: bool,
+    },
+    #[serde(rename = "door.state")]
+    DoorState {
+        door_id: u32,
+        state: MotorizedDoorState,
+    },
+    #[serde(rename = "door.opened")]
+    DoorOpened { door_id: u32 },
+    #[serde(rename = "door.closed")]
+    DoorClosed { door_id: u32 },
+    #[serde(rename = "door.forced_open")]
+    DoorForcedOpen { door_id: u32 },
+    #[serde(rename = "door.held_open")]
+    DoorHeldOpen { door_id: u32, open_seconds: u32 },
+    #[serde(rename = "door.request_to_exit")]
+    DoorRequestToExit { door_id: u32 },
+    // A relay output couldn't be driven outside of a command
+    #[serde(rename = "door.fault")]
+    DoorFault { door_id: u32, error: String },
+    // The new image passed its health check and is kept
+    #[serde(rename = "firmware.updated")]
+    FirmwareUpdated { sha256: String, version: String },
+    // The new image was rolled back to version
+    #[serde(rename = "firmware.rolled_back")]
+    FirmwareRolledBack { sha256: String, version: String },
+    // Sent once after boot, naming the worker thread if the watchdog restarted a wedged one
+    #[serde(rename = "system.reset")]
+    SystemReset {
+        reset_reason: String,
+        stalled_worker: Option<String>,
+    },
+    #[serde(rename = "journal.replay")]
+    JournalReplay {
+        sequence: u32,
+        boot: u32,
+        timestamp_ms: u64,
+        report: Box<MANAGEReport>,
+    },
+    #[serde(rename = "config.current")]
+    Config {
+        config: GuardianConfig,
+        restart_required: bool,
+    },
+    #[serde(rename = "config.rejected")]
+    ConfigRejected { error: String },
+    #[serde(rename = "auth.response")]
+    AuthResponse {
+        mac_address: String,
+        nonce: String,
+        signature: String,
+    },
+    #[serde(rename = "command.rejected")]
+    CommandRejected {
+        // Set once the signed payload could be read
+        request_id: Option<String>,
+        reason: String,
+    },
+    #[serde(rename = "command.result")]
+    CommandResult {
+        request_id: String,
+        result: CommandOutcome,
+    },
+}
+
+impl MANAGECommand {
+    // The door a door.* command is for
+    pub fn door_id(&self) -> Option<u32> {
+        match self {
+            MANAGECommand::DoorOpen { door_id }
+            | MANAGECommand::DoorClose { door_id }
+            | MANAGECommand::DoorStop { door_id }
+            | MANAGECommand::DoorUnlock { door_id, .. } => Some(*door_id),
+            _ => None,
+        }
+    }
+}
+
+impl MANAGEReport {
+    // Periodic reports are superseded by the next one and replies only matter to the
+    // connection that asked, neither is worth journaling
+    pub fn is_journaled(&self) -> bool {
+        !matches!(
+            self,
+            MANAGEReport::Hello { .. }
+                | MANAGEReport::Heartbeat { .. }
+                | MANAGEReport::Telemetry { .. }
+                | MANAGEReport::Config { .. }
+                | MANAGEReport::ConfigRejected { .. }
+                | MANAGEReport::AuthResponse { .. }
+        )
+    }
+
+    // Reports sent before the session is authenticated
+    pub fn is_handshake(&self) -> bool {
+        matches!(self, MANAGEReport::AuthResponse { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_door_commands() {
+        let command: MANAGECommand =
+            serde_json::from_str(r#"{"command": "door.unlock", "duration": 5}"#).unwrap();
+        assert!(matches!(
+            command,
+            MANAGECommand::DoorUnlock {
+                door_id: 0,
+                duration: 5
+            }
+        ));
+
+        let command: MANAGECommand =
+            serde_json::from_str(r#"{"command": "door.stop", "door_id": 1}"#).unwrap();
+        assert_eq!(command.door_id(), Some(1));
+    }
+
+    #[test]
+    fn parses_access_cache_and_journal_commands() {
+        let command: MANAGECommand = serde_json::from_str(
+            r#"{"command": "access_cache.set", "credentials": ["cafe"], "unlock_duration": 3}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            command,
+            MANAGECommand::AccessCacheSet { credentials, unlock_duration: 3 } if credentials == ["cafe"]
+        ));
+
+        let command: MANAGECommand =
+            serde_json::from_str(r#"{"command": "journal.ack", "sequence": 42}"#).unwrap();
+        assert!(matches!(
+            command,
+            MANAGECommand::JournalAck { sequence: 42 }
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_and_incomplete_commands() {
+        assert!(serde_json::from_str::<MANAGECommand>(r#"{"command": "door.explode"}"#).is_err());
+        assert!(serde_json::from_str::<MANAGECommand>(r#"{"command": "door.unlock"}"#).is_err());
+    }
+
+    #[test]
+    fn supported_commands_are_all_known() {
+        for command in SUPPORTED_COMMANDS {
+            let json = format!(r#"{{"command": "{}"}}"#, command);
+            if let Err(error) = serde_json::from_str::<MANAGECommand>(&json) {
+                assert!(!error.to_string().contains("unknown variant"), "{}", error);
+            }
+        }
+    }
+
+    // The tag of every variant, a new variant doesn't build until it is named here and in
+    // SUPPORTED_COMMANDS
+    fn command_tag(command: &MANAGECommand) -> &'static str {
+        match command {
+            MANAGECommand::DoorOpen { .. } => "door.open",
+            MANAGECommand::DoorClose { .. } => "door.close",
+            MANAGECommand::DoorStop { .. } => "door.stop",
+            MANAGECommand::DoorUnlock { .. } => "door.unlock",
+            MANAGECommand::AccessCacheSet { .. } => "access_cache.set",
+            MANAGECommand::AccessCacheAdd { .. } => "access_cache.add",
+            MANAGECommand::AccessCacheRemove { .. } => "access_cache.remove",
+            MANAGECommand::AccessCacheClear => "access_cache.clear",
+            MANAGECommand::JournalAck { .. } => "journal.ack",
+            MANAGECommand::ConfigSet { .. } => "config.set",
+            MANAGECommand::ConfigGet => "config.get",
+            MANAGECommand::ReaderLed { .. } => "reader.led",
+            MANAGECommand::ReaderBuzzer { .. } => "reader.buzzer",
+            MANAGECommand::ReaderText { .. } => "reader.text",
+            MANAGECommand::FirmwareUpdate { .. } => "firmware.update",
+            MANAGECommand::AuthChallenge { .. } => "auth.challenge",
+            MANAGECommand::AuthAccepted { .. } => "auth.accepted",
+            MANAGECommand::Signed { .. } => "signed",
+        }
+    }
+
+    #[test]
+    fn supported_commands_cover_every_variant() {
+        let commands = [
+            MANAGECommand::DoorOpen { door_id: 0 },
+            MANAGECommand::DoorClose { door_id: 0 },
+            MANAGECommand::DoorStop { door_id: 0 },
+            MANAGECommand::DoorUnlock {
+                door_id: 0,
+                duration: 0,
+            },
+            MANAGECommand::AccessCacheSet {
+                credentials: Vec::new(),
+                unlock_duration: 0,
+            },
+            MANAGECommand::AccessCacheAdd {
+                credential: String::new(),
+            },
+            MANAGECommand::AccessCacheRemove {
+                credential: String::new(),
+            },
+            MANAGECommand::AccessCacheClear,
+            MANAGECommand::JournalAck { sequence: 0 },
+            MANAGECommand::ConfigSet {
+                config: Value::Null,
+            },
+            MANAGECommand::ConfigGet,
+            MANAGECommand::ReaderLed {
+                reader: 0,
+                color: ReaderLedColor::Off,
+                duration_ms: 0,
+            },
+            MANAGECommand::ReaderBuzzer {
+                reader: 0,
+                count: 0,
+                on_ms: 0,
+                off_ms: 0,
+            },
+            MANAGECommand::ReaderText {
+                reader: 0,
+                text: String::new(),
+                duration_secs: 0,
+            },
+            MANAGECommand::FirmwareUpdate {
+                url: String::new(),
+                sha256: String::new(),
+                signature: String::new(),
+            },
+            MANAGECommand::AuthChallenge {
+                nonce: String::new(),
+            },
+            MANAGECommand::AuthAccepted {
+                signature: String::new(),
+            },
+            MANAGECommand::Signed {
+                payload: String::new(),
+                signature: String::new(),
+            },
+        ];
+        assert_eq!(commands.each_ref().map(command_tag), SUPPORTED_COMMANDS);
+
+        // The tags above are the ones serde reads and writes
+        for command in &commands {
+            let json = serde_json::to_value(command).unwrap();
+            assert_eq!(json["command"], command_tag(command));
+        }
+    }
+
+    #[test]
+    fn parses_signed_command_payloads() {
+        let payload: CommandPayload = serde_json::from_str(
+            r#"{"request_id": "r1", "counter": 7, "timestamp": 1700000000, "command": "door.unlock", "duration": 5}"#,
+        )
+        .unwrap();
+        assert_eq!(payload.request_id, "r1");
+        assert_eq!(payload.counter, 7);
+        assert_eq!(payload.timestamp, 1700000000);
+        assert!(matches!(
+            payload.command,
+            MANAGECommand::DoorUnlock { duration: 5, .. }
+        ));
+    }
+
+    #[test]
+    fn serializes_reports_with_command_tag() {
+        let report = MANAGEReport::DoorState {
+            door_id: 1,
+            state: MotorizedDoorState::Opening,
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"command":"door.state","door_id":1,"state":"opening"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&MANAGEReport::Heartbeat { is_healthy: true }).unwrap(),
+            r#"{"command":"heartbeat","is_healthy":true}"#
+        );
+        let report = MANAGEReport::OsdpLocalStatus {
+            reader: 0,
+            tamper: true,
+            power_failure: false,
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"command":"osdp.local_status","reader":0,"tamper":true,"power_failure":false}"#
+        );
+        let report = MANAGEReport::CommandResult {
+            request_id: "r1".to_string(),
+            result: CommandOutcome::HardwareError {
+                error: "relay".to_string(),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"command":"command.result","request_id":"r1","result":{"status":"hardware_error","error":"relay"}}"#
+        );
+    }
+}
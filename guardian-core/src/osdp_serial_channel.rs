@@ -84,3 +84,54 @@ impl Channel for SerialChannel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpmc::sync_channel;
+
+    use super::*;
+
+    #[test]
+    fn reads_what_is_queued() {
+        let (tx_sender, _tx_receiver) = sync_channel::<u8>(4);
+        let (rx_sender, rx_receiver) = sync_channel::<u8>(4);
+        let mut channel = SerialChannel::new(1, tx_sender, rx_receiver);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(channel.read(&mut buf).unwrap(), 0);
+
+        rx_sender.send(0x53).unwrap();
+        rx_sender.send(0x80).unwrap();
+        assert_eq!(channel.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf[..2], [0x53, 0x80]);
+    }
+
+    #[test]
+    fn write_stops_when_the_queue_is_full() {
+        let (tx_sender, tx_receiver) = sync_channel::<u8>(2);
+        let (_rx_sender, rx_receiver) = sync_channel::<u8>(2);
+        let mut channel = SerialChannel::new(1, tx_sender, rx_receiver);
+
+        assert_eq!(channel.write(&[1, 2, 3]).unwrap(), 2);
+        assert!(matches!(channel.write(&[4]), Err(ChannelError::WouldBlock)));
+        assert_eq!(tx_receiver.try_iter().collect::<Vec<_>>(), [1, 2]);
+        channel.flush().unwrap();
+    }
+
+    #[test]
+    fn disconnected_queues_are_transport_errors() {
+        let (tx_sender, tx_receiver) = sync_channel::<u8>(2);
+        let (rx_sender, rx_receiver) = sync_channel::<u8>(2);
+        let mut channel = SerialChannel::new(1, tx_sender, rx_receiver);
+        drop((tx_receiver, rx_sender));
+
+        assert!(matches!(
+            channel.read(&mut [0u8; 1]),
+            Err(ChannelError::TransportError)
+        ));
+        assert!(matches!(
+            channel.write(&[1]),
+            Err(ChannelError::TransportError)
+        ));
+    }
+}
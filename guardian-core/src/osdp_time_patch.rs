@@ -10,3 +10,8 @@ pub extern "C" fn osdp_millis_now() -> i64 {
     let elapsed = START.elapsed();
     elapsed.as_millis() as i64
 }
+
+// Start OSDP time at boot rather than at the first libosdp call
+pub fn start() {
+    lazy_static::initialize(&START);
+}
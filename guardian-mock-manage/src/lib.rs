@@ -0,0 +1,8 @@
+//! Stand-in for the MANAGE door command websocket.
+//!
+//! Accepts Guardian connections on `/ws/office-security/door-commands/<mac>/`,
+//! plays a script of commands to every door that connects and records the
+//! reports it receives, so the simulator can be tested without the real
+//! MANAGE endpoint.
+
+pub mod manage_mock_server;
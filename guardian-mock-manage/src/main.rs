@@ -0,0 +1,96 @@
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+
+use guardian_core::manage_command::MANAGECommand;
+use guardian_mock_manage::manage_mock_server::{load_script, MockManage, DEV_DEVICE_SECRET};
+use native_tls::{Identity, TlsAcceptor};
+
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+
+const USAGE: &str = "Usage: guardian-mock-manage [--bind <addr:port>] [--script <file>] [--tls-cert <pem> --tls-key <pem>] [--device-secret <hex>]";
+
+struct MockArgs {
+    bind: String,
+    script: Option<String>,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    device_secret: Vec<u8>,
+}
+
+impl MockArgs {
+    fn parse() -> Self {
+        let mut mock_args = MockArgs {
+            bind: DEFAULT_BIND.to_string(),
+            script: None,
+            tls_cert: None,
+            tls_key: None,
+            device_secret: DEV_DEVICE_SECRET.to_vec(),
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bind" => mock_args.bind = args.next().expect(USAGE),
+                "--script" => mock_args.script = Some(args.next().expect(USAGE)),
+                "--tls-cert" => mock_args.tls_cert = Some(args.next().expect(USAGE)),
+                "--tls-key" => mock_args.tls_key = Some(args.next().expect(USAGE)),
+                "--device-secret" => {
+                    mock_args.device_secret = hex::decode(args.next().expect(USAGE)).expect(USAGE)
+                }
+                _ => {
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
+                }
+            }
+        }
+        mock_args
+    }
+}
+
+fn main() {
+    // Log to stderr, info and up unless RUST_LOG says otherwise
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = MockArgs::parse();
+
+    // Load the command script played to every door
+    let script = match &args.script {
+        Some(path) => load_script(Path::new(path)).expect("Failed to load script"),
+        None => Vec::new(),
+    };
+
+    // Serve wss:// when given a (self-signed) certificate and its PKCS#8 key
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => {
+            let cert = fs::read(cert).expect("Failed to read TLS certificate");
+            let key = fs::read(key).expect("Failed to read TLS key");
+            let identity = Identity::from_pkcs8(&cert, &key).expect("Invalid TLS certificate");
+            Some(TlsAcceptor::new(identity).expect("Failed to setup TLS"))
+        }
+        (None, None) => None,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mock = MockManage::start(&args.bind, tls, script, args.device_secret)
+        .expect("Failed to start mock MANAGE");
+
+    // Every line typed is sent as a command to all connected doors
+    log::info!("Type commands as JSON, e.g. {{\"command\":\"door.unlock\",\"duration\":5}}");
+    for line in std::io::stdin().lock().lines() {
+        let line = line.expect("Failed to read console");
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<MANAGECommand>(&line) {
+            Ok(command) => mock.send_command(&command),
+            Err(error) => log::error!("Invalid command: {:?}", error),
+        }
+    }
+
+    // Keep serving once stdin is closed
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,480 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use guardian_core::guardian_device_identity::{
+    command_signature, device_signature, manage_signature,
+};
+use guardian_core::manage_command::{CommandPayload, MANAGECommand, MANAGEReport};
+use native_tls::TlsAcceptor;
+use serde::Deserialize;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+
+pub const DOOR_COMMANDS_PATH: &str = "/ws/office-security/door-commands/";
+
+// Development secret shared with guardian-sim, never provision this on a real door
+pub const DEV_DEVICE_SECRET: &[u8; 32] = b"guardian-sim-dev-secret-00000000";
+
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Command sent to each door once it has been connected for the given delay
+#[derive(Deserialize, Debug)]
+pub struct ScriptStep {
+    // Delay after the previous step, in milliseconds
+    #[serde(default)]
+    pub delay_ms: u64,
+    pub command: MANAGECommand,
+}
+
+// Load a script with one step per line, blank lines and lines starting with # are skipped
+pub fn load_script(path: &Path) -> io::Result<Vec<ScriptStep>> {
+    fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceivedReport {
+    pub mac_address: String,
+    pub report: MANAGEReport,
+}
+
+impl ReceivedReport {
+    // The report as originally raised, with journal replays unwrapped
+    pub fn original(&self) -> &MANAGEReport {
+        let mut report = &self.report;
+        while let MANAGEReport::JournalReplay { report: inner, .. } = report {
+            report = inner;
+        }
+        report
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    connected: Vec<String>,
+    reports: Vec<ReceivedReport>,
+}
+
+type SharedState = Arc<(Mutex<MockState>, Condvar)>;
+type Connections = Arc<Mutex<Vec<Sender<MANAGECommand>>>>;
+
+pub struct MockManage {
+    local_addr: SocketAddr,
+    tls: bool,
+    state: SharedState,
+    connections: Connections,
+}
+
+impl MockManage {
+    // Start listening, use port 0 to pick a free port
+    //
+    // Every door is expected to hold device_secret, doors that fail the handshake are dropped
+    pub fn start(
+        bind: &str,
+        tls: Option<TlsAcceptor>,
+        script: Vec<ScriptStep>,
+        device_secret: Vec<u8>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(bind)?;
+        let local_addr = listener.local_addr()?;
+        let state = SharedState::default();
+        let connections = Connections::default();
+
+        // Every connection replays the script from the start
+        let script: Arc<Vec<(Duration, MANAGECommand)>> = Arc::new(
+            script
+                .into_iter()
+                .map(|step| (Duration::from_millis(step.delay_ms), step.command))
+                .collect(),
+        );
+
+        let mock = Self {
+            local_addr,
+            tls: tls.is_some(),
+            state: state.clone(),
+            connections: connections.clone(),
+        };
+        let tls = tls.map(Arc::new);
+        let device_secret = Arc::new(device_secret);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        log::error!("Failed to accept connection: {:?}", error);
+                        continue;
+                    }
+                };
+                let (tls, state, connections, script, device_secret) = (
+                    tls.clone(),
+                    state.clone(),
+                    connections.clone(),
+                    script.clone(),
+                    device_secret.clone(),
+                );
+                thread::spawn(move || {
+                    let (command_tx, command_rx) = channel();
+                    connections.lock().unwrap().push(command_tx);
+                    let door = DoorSession {
+                        state: &state,
+                        script: &script,
+                        command_rx: &command_rx,
+                        device_secret: &device_secret,
+                    };
+                    match tls {
+                        Some(tls) => match tls.accept(stream) {
+                            Ok(stream) => {
+                                stream
+                                    .get_ref()
+                                    .set_read_timeout(Some(READ_TIMEOUT))
+                                    .unwrap();
+                                serve_door(stream, &door);
+                            }
+                            Err(error) => log::warn!("TLS handshake failed: {:?}", error),
+                        },
+                        None => {
+                            stream.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+                            serve_door(stream, &door);
+                        }
+                    }
+                });
+            }
+        });
+
+        log::info!("Mock MANAGE listening on {}", mock.ws_base_uri());
+        Ok(mock)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Base URI to point Guardian at, the MAC address is appended by the door
+    pub fn ws_base_uri(&self) -> String {
+        let scheme = if self.tls { "wss" } else { "ws" };
+        format!("{}://{}{}", scheme, self.local_addr, DOOR_COMMANDS_PATH)
+    }
+
+    // Send a command to every connected door
+    pub fn send_command(&self, command: &MANAGECommand) {
+        self.connections
+            .lock()
+            .unwrap()
+            .retain(|command_tx| command_tx.send(command.clone()).is_ok());
+    }
+
+    pub fn connected(&self) -> Vec<String> {
+        let (lock, _) = &*self.state;
+        lock.lock().unwrap().connected.clone()
+    }
+
+    pub fn reports(&self) -> Vec<ReceivedReport> {
+        let (lock, _) = &*self.state;
+        lock.lock().unwrap().reports.clone()
+    }
+
+    // Wait until a door has connected, returning its MAC address
+    pub fn wait_for_connection(&self, timeout: Duration) -> Option<String> {
+        self.wait_for(timeout, |state| state.connected.first().cloned())
+    }
+
+    // Wait for a report matching the predicate, journal replays are matched on the original report
+    pub fn wait_for_report<F>(&self, timeout: Duration, predicate: F) -> Option<ReceivedReport>
+    where
+        F: Fn(&MANAGEReport) -> bool,
+    {
+        self.wait_for(timeout, |state| {
+            state
+                .reports
+                .iter()
+                .find(|received| predicate(received.original()))
+                .cloned()
+        })
+    }
+
+    fn wait_for<T, F>(&self, timeout: Duration, check: F) -> Option<T>
+    where
+        F: Fn(&MockState) -> Option<T>,
+    {
+        let deadline = Instant::now() + timeout;
+        let (lock, condvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if let Some(found) = check(&state) {
+                return Some(found);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            state = condvar.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+}
+
+// Extract the MAC address from /ws/office-security/door-commands/<mac>/
+fn mac_address_from_path(path: &str) -> Option<String> {
+    let mac_address = path.strip_prefix(DOOR_COMMANDS_PATH)?.strip_suffix('/')?;
+    if mac_address.is_empty() || mac_address.contains('/') {
+        return None;
+    }
+    Some(mac_address.to_string())
+}
+
+// Everything a connection thread needs to serve one door
+struct DoorSession<'a> {
+    state: &'a SharedState,
+    script: &'a [(Duration, MANAGECommand)],
+    command_rx: &'a Receiver<MANAGECommand>,
+    device_secret: &'a [u8],
+}
+
+// Signs commands for one authenticated connection
+struct CommandSigner<'a> {
+    mac_address: &'a str,
+    device_secret: &'a [u8],
+    manage_nonce: String,
+    device_nonce: String,
+    counter: u64,
+}
+
+impl CommandSigner<'_> {
+    fn sign(&mut self, command: MANAGECommand) -> MANAGECommand {
+        self.counter += 1;
+        let payload = serde_json::to_string(&CommandPayload {
+            request_id: format!("{}-{}", self.mac_address, self.counter),
+            counter: self.counter,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            command,
+        })
+        .unwrap();
+        let signature = command_signature(
+            self.device_secret,
+            self.mac_address,
+            &self.manage_nonce,
+            &self.device_nonce,
+            &payload,
+        );
+        MANAGECommand::Signed { payload, signature }
+    }
+}
+
+// The handshake callback signature, including its large error type, is set by tungstenite
+#[allow(clippy::result_large_err)]
+fn serve_door<S: Read + Write>(stream: S, door: &DoorSession) {
+    // Only accept the door command path
+    let mut mac_address = None;
+    let callback = |request: &Request, response: Response| {
+        mac_address = mac_address_from_path(request.uri().path());
+        if mac_address.is_none() {
+            log::warn!("Rejected connection to {}", request.uri());
+            let mut error = ErrorResponse::new(Some("Unknown door command path".to_string()));
+            *error.status_mut() = StatusCode::NOT_FOUND;
+            return Err(error);
+        }
+        Ok(response)
+    };
+    // Reads time out so commands can be sent while waiting, retry until the handshake completes
+    let mut handshake = tungstenite::accept_hdr(stream, callback);
+    let mut websocket = loop {
+        match handshake {
+            Ok(websocket) => break websocket,
+            Err(HandshakeError::Interrupted(mid_handshake)) => {
+                handshake = mid_handshake.handshake();
+            }
+            Err(HandshakeError::Failure(error)) => {
+                log::warn!("Websocket handshake failed: {:?}", error);
+                return;
+            }
+        }
+    };
+    let mac_address = mac_address.unwrap();
+
+    // Nothing is exchanged with a door that can't prove its identity
+    let Ok(mut signer) = authenticate(&mut websocket, &mac_address, door.device_secret) else {
+        log::warn!("Door {} failed to authenticate", mac_address);
+        return;
+    };
+    log::info!("Door {} connected", mac_address);
+
+    let (lock, condvar) = &**door.state;
+    lock.lock().unwrap().connected.push(mac_address.clone());
+    condvar.notify_all();
+
+    // Runs until the door disconnects or a command can't be sent
+    let _ = exchange(&mut websocket, &mut signer, door);
+
+    log::info!("Door {} disconnected", mac_address);
+    lock.lock()
+        .unwrap()
+        .connected
+        .retain(|connected| *connected != mac_address);
+}
+
+// Challenge the door to prove it holds the device secret, then prove we hold it too
+fn authenticate<'a, S: Read + Write>(
+    websocket: &mut WebSocket<S>,
+    mac_address: &'a str,
+    device_secret: &'a [u8],
+) -> Result<CommandSigner<'a>, ()> {
+    let mut manage_nonce = [0u8; 16];
+    getrandom::getrandom(&mut manage_nonce).unwrap();
+    let manage_nonce = hex::encode(manage_nonce);
+    let challenge = MANAGECommand::AuthChallenge {
+        nonce: manage_nonce.clone(),
+    };
+    send_command(websocket, mac_address, &challenge)?;
+
+    let deadline = Instant::now() + AUTH_TIMEOUT;
+    while Instant::now() < deadline {
+        match websocket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str::<MANAGEReport>(&text) {
+                Ok(MANAGEReport::AuthResponse {
+                    mac_address: claimed,
+                    nonce,
+                    signature,
+                }) => {
+                    let expected =
+                        device_signature(device_secret, mac_address, &manage_nonce, &nonce);
+                    if claimed != mac_address || signature != expected {
+                        return Err(());
+                    }
+                    let accepted = MANAGECommand::AuthAccepted {
+                        signature: manage_signature(
+                            device_secret,
+                            mac_address,
+                            &manage_nonce,
+                            &nonce,
+                        ),
+                    };
+                    send_command(websocket, mac_address, &accepted)?;
+                    return Ok(CommandSigner {
+                        mac_address,
+                        device_secret,
+                        manage_nonce,
+                        device_nonce: nonce,
+                        counter: 0,
+                    });
+                }
+                _ => log::warn!(
+                    "Dropped report from unauthenticated {}: {}",
+                    mac_address,
+                    text
+                ),
+            },
+            Ok(Message::Close(_)) => return Err(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut => {}
+            Err(_) => return Err(()),
+        }
+    }
+    Err(())
+}
+
+fn exchange<S: Read + Write>(
+    websocket: &mut WebSocket<S>,
+    signer: &mut CommandSigner,
+    door: &DoorSession,
+) -> Result<(), ()> {
+    let mac_address = signer.mac_address;
+    let (lock, condvar) = &**door.state;
+    let command_rx = door.command_rx;
+    let mut script = door.script.iter().peekable();
+    let mut next_step = Instant::now();
+    loop {
+        // Play scripted commands as they come due
+        while let Some((delay, command)) = script.peek() {
+            if Instant::now() < next_step + *delay {
+                break;
+            }
+            next_step += *delay;
+            send_command(websocket, mac_address, &signer.sign(command.clone()))?;
+            script.next();
+        }
+
+        // Send commands queued through send_command
+        while let Ok(command) = command_rx.try_recv() {
+            send_command(websocket, mac_address, &signer.sign(command))?;
+        }
+
+        match websocket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str::<MANAGEReport>(&text) {
+                Ok(report) => {
+                    log::info!("Report from {}: {}", mac_address, text);
+
+                    // Acknowledge replays so the door can drop them from its journal
+                    if let MANAGEReport::JournalReplay { sequence, .. } = report {
+                        let ack = signer.sign(MANAGECommand::JournalAck { sequence });
+                        send_command(websocket, mac_address, &ack)?;
+                    }
+
+                    lock.lock().unwrap().reports.push(ReceivedReport {
+                        mac_address: mac_address.to_string(),
+                        report,
+                    });
+                    condvar.notify_all();
+                }
+                Err(error) => {
+                    log::error!("Failed to decode report from {}: {:?}", mac_address, error);
+                }
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut => {}
+            Err(error) => {
+                log::warn!("Connection to {} failed: {:?}", mac_address, error);
+                return Err(());
+            }
+        }
+    }
+}
+
+fn send_command<S: Read + Write>(
+    websocket: &mut WebSocket<S>,
+    mac_address: &str,
+    command: &MANAGECommand,
+) -> Result<(), ()> {
+    let text = serde_json::to_string(command).unwrap();
+    log::info!("Command to {}: {}", mac_address, text);
+    websocket
+        .send(Message::Text(text))
+        .map_err(|error| log::warn!("Failed to send command to {}: {:?}", mac_address, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn door_command_path_carries_the_mac_address() {
+        assert_eq!(
+            mac_address_from_path("/ws/office-security/door-commands/02005e000001/"),
+            Some("02005e000001".to_string())
+        );
+        assert_eq!(
+            mac_address_from_path("/ws/office-security/door-commands/"),
+            None
+        );
+        assert_eq!(mac_address_from_path("/ws/other/02005e000001/"), None);
+    }
+}
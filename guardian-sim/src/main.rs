@@ -0,0 +1,370 @@
+#![feature(mpmc_channel)]
+
+//! Runs the Guardian threads on the host against simulated hardware.
+//!
+//! The OSDP reader is a libosdp peripheral device on the other end of an
+//! in-process pipe, door relays are logged instead of switched and the door
+//! inputs are driven from the console.
+
+use std::fs;
+use std::sync::atomic::Ordering;
+use std::sync::mpmc::sync_channel;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use guardian_core::aperture_door_security::{
+    DoorSecurity, DoorSecurityInputs, DoorSecurityOutputs,
+};
+use guardian_core::guardian_access_cache::AccessCache;
+use guardian_core::guardian_command_router::CommandRouter;
+use guardian_core::guardian_config::{ConfigStore, DoorConfig, DoorKind, GuardianConfig};
+use guardian_core::guardian_device_identity::{DeviceIdentity, ManageSession};
+use guardian_core::guardian_firmware_update::FirmwareUpdater;
+use guardian_core::guardian_global_status::MANAGE_CONNECTED;
+use guardian_core::guardian_hal::{SharedClock, SystemClock, SystemWatchdog};
+use guardian_core::guardian_hello::ControllerInfo;
+use guardian_core::guardian_manage_link::ManageLink;
+use guardian_core::guardian_osdp_events::OsdpEventHandler;
+use guardian_core::guardian_osdp_secure_channel::SecureChannel;
+use guardian_core::guardian_reader_feedback::ReaderFeedback;
+use guardian_core::guardian_report_journal::ReportJournal;
+use guardian_core::guardian_runtime;
+use guardian_core::guardian_storage::{GuardianStorage, MemoryStore};
+use guardian_core::guardian_telemetry::TelemetrySources;
+use guardian_core::guardian_watchdog::Watchdog;
+use guardian_core::manage_command::{CommandRequest, MANAGEReport};
+use guardian_core::osdp_serial_channel::SerialChannel;
+use guardian_core::osdp_time_patch;
+use libosdp::{ControlPanel, OsdpEvent};
+use native_tls::{Certificate, TlsConnector};
+use sim_console::SimInputs;
+use sim_firmware::SimFirmwareSlots;
+use sim_gpio::{SimInputPin, SimOutputPin};
+use sim_metrics::SimSystemMetrics;
+use sim_watchdog::SimSystemWatchdog;
+use sim_ws_transport::SimWsTransport;
+
+mod sim_console;
+mod sim_firmware;
+mod sim_gpio;
+mod sim_metrics;
+mod sim_pd;
+mod sim_watchdog;
+mod sim_ws_transport;
+
+// Core Parameters
+const DEFAULT_WS_BASE_URI: &str = "ws://localhost:8080/ws/office-security/door-commands/";
+const DEFAULT_MAC_ADDRESS: &str = "02005e000001";
+// Development secret shared with guardian-mock-manage, never provision this on a real door
+const DEFAULT_DEVICE_SECRET: &str =
+    "677561726469616e2d73696d2d6465762d7365637265742d3030303030303030";
+const MANAGE_LOOP_INTERVAL: Duration = Duration::from_millis(100);
+
+const USAGE: &str = "Usage: guardian-sim [--manage <ws-base-uri>] [--ca-cert <pem>] [--mac <hex>] \
+                     [--device-secret <hex>] [--motorized]";
+
+struct SimArgs {
+    ws_base_uri: String,
+    ca_cert: Option<String>,
+    mac_address: String,
+    device_secret: Vec<u8>,
+    motorized: bool,
+}
+
+impl SimArgs {
+    fn parse() -> Self {
+        let mut sim_args = SimArgs {
+            ws_base_uri: DEFAULT_WS_BASE_URI.to_string(),
+            ca_cert: None,
+            mac_address: DEFAULT_MAC_ADDRESS.to_string(),
+            device_secret: hex::decode(DEFAULT_DEVICE_SECRET).unwrap(),
+            motorized: false,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--manage" => sim_args.ws_base_uri = args.next().expect(USAGE),
+                "--ca-cert" => sim_args.ca_cert = Some(args.next().expect(USAGE)),
+                "--mac" => sim_args.mac_address = args.next().expect(USAGE),
+                "--device-secret" => {
+                    sim_args.device_secret = hex::decode(args.next().expect(USAGE)).expect(USAGE)
+                }
+                "--motorized" => sim_args.motorized = true,
+                _ => {
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
+                }
+            }
+        }
+        sim_args
+    }
+}
+
+fn main() {
+    // Log to stderr, info and up unless RUST_LOG says otherwise
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = SimArgs::parse();
+
+    // Report Start
+    log::info!("Initializing Guardian Simulator...");
+    osdp_time_patch::start();
+    let booted_at = Instant::now();
+
+    // Setup channel for commands to the simulated door
+    let (command_channel_tx, command_channel_rx) = mpsc::channel::<CommandRequest>();
+
+    // Setup channel for report data
+    let (report_channel_tx, report_channel_rx) = mpsc::channel::<MANAGEReport>();
+
+    // Shared monotonic clock
+    let clock: SharedClock = Arc::new(SystemClock);
+
+    // Simulated door with every input fitted, stored so ConfigGet reports what is running
+    let mut door = DoorConfig {
+        limit_switches_fitted: args.motorized,
+        door_position_sensor_fitted: true,
+        request_to_exit_fitted: true,
+        ..Default::default()
+    };
+    if args.motorized {
+        door.door_type = DoorKind::Motorized;
+    }
+    let config = GuardianConfig {
+        ws_base_uri: args.ws_base_uri.clone(),
+        doors: vec![door],
+        ..Default::default()
+    };
+    let door = &config.doors[0];
+    let mut config_storage = GuardianStorage::new(MemoryStore::default());
+    config.store(&mut config_storage);
+    let config_store = ConfigStore::load(config_storage, report_channel_tx.clone());
+    let config_store = Arc::new(Mutex::new(config_store));
+
+    // Offline access cache and report journal live in memory for a simulator run
+    let access_cache = Arc::new(Mutex::new(AccessCache::load(GuardianStorage::new(
+        MemoryStore::default(),
+    ))));
+    let report_journal = Arc::new(Mutex::new(ReportJournal::load(
+        GuardianStorage::new(MemoryStore::default()),
+        clock.clone(),
+    )));
+
+    // Authenticate with MANAGE using the secret from the command line
+    let identity = DeviceIdentity::new(args.mac_address.clone(), args.device_secret);
+    let controller_info = ControllerInfo::new(
+        env!("CARGO_PKG_VERSION"),
+        None,
+        SimSystemWatchdog.reset_reason(),
+        args.mac_address.clone(),
+        &config,
+        booted_at,
+    );
+    let manage_session = Arc::new(Mutex::new(ManageSession::new(
+        identity.clone(),
+        report_channel_tx.clone(),
+        &MANAGE_CONNECTED,
+    )));
+
+    // Setup channel for reader LED, buzzer and text commands
+    let (reader_command_tx, reader_command_rx) = mpsc::channel::<CommandRequest>();
+
+    // Supervise the worker threads
+    let mut watchdog = Watchdog::new(
+        SimSystemWatchdog,
+        GuardianStorage::new(MemoryStore::default()),
+        report_channel_tx.clone(),
+    );
+
+    // Handle firmware updates, which the simulator turns down
+    let (firmware_command_tx, firmware_command_rx) = mpsc::channel::<CommandRequest>();
+    let firmware_updater = FirmwareUpdater::new(
+        SimFirmwareSlots,
+        identity,
+        GuardianStorage::new(MemoryStore::default()),
+        report_channel_tx.clone(),
+        Instant::now(),
+    );
+    guardian_runtime::spawn_firmware_update(
+        firmware_updater,
+        firmware_command_rx,
+        report_channel_tx.clone(),
+        watchdog.register("firmware_update"),
+    );
+
+    // Setup command router for incoming MANAGE commands
+    let command_router = Arc::new(CommandRouter::new(
+        vec![command_channel_tx.clone()],
+        reader_command_tx.clone(),
+        Some(firmware_command_tx),
+        report_channel_tx.clone(),
+        access_cache.clone(),
+        report_journal.clone(),
+        config_store,
+        manage_session,
+    ));
+
+    // Initialize virtual door inputs in their idle state
+    let limit_switch_active_high = door.limit_switch_active_high;
+    let inputs = SimInputs {
+        open_limit: SimInputPin::new(!limit_switch_active_high),
+        closed_limit: SimInputPin::new(limit_switch_active_high),
+        door_position: SimInputPin::new(!door.door_position_active_high),
+        request_to_exit: SimInputPin::new(!door.request_to_exit_active_high),
+        limit_switch_active_high,
+        door_position_active_high: door.door_position_active_high,
+        request_to_exit_active_high: door.request_to_exit_active_high,
+    };
+
+    // Initialize the door security handler
+    let door_security = DoorSecurity::new(
+        0,
+        door.door_type(),
+        DoorSecurityOutputs {
+            open: SimOutputPin::new("open"),
+            close: SimOutputPin::new("close"),
+            stop_unlock: SimOutputPin::new("stop/unlock"),
+        },
+        DoorSecurityInputs {
+            open_limit: door
+                .limit_switches_fitted
+                .then(|| inputs.open_limit.clone()),
+            closed_limit: door
+                .limit_switches_fitted
+                .then(|| inputs.closed_limit.clone()),
+            door_position: Some(inputs.door_position.clone()),
+            request_to_exit: Some(inputs.request_to_exit.clone()),
+        },
+        door.door_input_config(),
+        ReaderFeedback::new(
+            config.reader_feedback.clone(),
+            config.door_readers(0),
+            reader_command_tx.clone(),
+        ),
+        report_channel_tx.clone(),
+        clock.clone(),
+    );
+    log::info!("Simulated Door Initialized");
+
+    // Connect the control panel and the simulated reader with a pair of byte pipes
+    let (cp_to_pd_tx, cp_to_pd_rx) = sync_channel::<u8>(256);
+    let (pd_to_cp_tx, pd_to_cp_rx) = sync_channel::<u8>(256);
+    let cp_to_pd_queue = cp_to_pd_rx.clone();
+    let pd_channel = Box::new(SerialChannel::new(1, pd_to_cp_tx, cp_to_pd_rx));
+
+    // Start the simulated reader
+    let (reader_event_tx, reader_event_rx) = mpsc::channel::<OsdpEvent>();
+    sim_pd::spawn_peripheral_device(pd_channel, reader_event_rx);
+
+    // Reader keys live in memory, so every run installs a new key on the simulated reader
+    let osdp_key_storage = Arc::new(Mutex::new(GuardianStorage::new(MemoryStore::default())));
+    let secure_channels: Vec<SecureChannel> = (0..config.readers.len() as i32)
+        .map(|reader| {
+            SecureChannel::load(
+                reader,
+                config.osdp_secure_channel,
+                osdp_key_storage.clone(),
+                report_channel_tx.clone(),
+            )
+        })
+        .collect();
+
+    // Initialize OSDP Control Panel, the simulated reader answers at the default address
+    let pd_infos = guardian_runtime::reader_pd_infos(
+        &config.readers,
+        config.osdp_baud_rate,
+        &secure_channels,
+        || {
+            Box::new(SerialChannel::new(
+                1,
+                cp_to_pd_tx.clone(),
+                pd_to_cp_rx.clone(),
+            ))
+        },
+    );
+    let cp = ControlPanel::new(pd_infos).expect("Failed to initialize Control Panel");
+    log::info!("OSDP Control Panel Initialized");
+
+    // Create thread to handle OSDP CP events & other tasks
+    let osdp_event_handler = OsdpEventHandler::new(
+        access_cache,
+        report_channel_tx.clone(),
+        vec![command_channel_tx.clone()],
+        config.readers.clone(),
+        &MANAGE_CONNECTED,
+    );
+    guardian_runtime::spawn_osdp_control_panel(
+        cp,
+        osdp_event_handler,
+        secure_channels,
+        reader_command_rx,
+        report_channel_tx.clone(),
+        watchdog.register("osdp"),
+    );
+
+    // Initialize the door security last tick time and state
+    let door_security_last_tick = watchdog.register("door_0");
+    let door_status = Arc::new(Mutex::new(door_security.status()));
+
+    // Create thread to handle door system
+    guardian_runtime::spawn_door_security(
+        door_security,
+        command_channel_rx,
+        door_security_last_tick.clone(),
+        door_status.clone(),
+    );
+
+    // Create thread to handle system health and telemetry
+    let manage_last_tick = watchdog.register("manage");
+    let system_health_last_tick = watchdog.register("system_health");
+    let telemetry = TelemetrySources {
+        workers: watchdog.worker_ticks(),
+        doors: vec![door_status],
+        osdp_queues: vec![
+            ("tx".to_string(), cp_to_pd_queue),
+            ("rx".to_string(), pd_to_cp_rx.clone()),
+        ],
+    };
+    guardian_runtime::spawn_system_health(
+        vec![door_security_last_tick],
+        report_channel_tx.clone(),
+        config.heartbeat_interval(),
+        config.readers.len(),
+        SimSystemMetrics,
+        telemetry,
+        system_health_last_tick,
+    );
+
+    // Create thread to end the simulator if any of the threads above wedges
+    guardian_runtime::spawn_watchdog(watchdog);
+
+    // Drive the door inputs and the reader from the console
+    sim_console::spawn_console(inputs, reader_event_tx);
+    log::info!("Guardian Simulator Initialization Complete!");
+
+    // Connect to MANAGE Door Security Websocket
+    let ws_uri = format!("{}{}/", config.ws_base_uri, args.mac_address);
+    let tls = args.ca_cert.map(|ca_cert| {
+        let ca_cert = fs::read(ca_cert).expect("Failed to read CA certificate");
+        let ca_cert = Certificate::from_pem(&ca_cert).expect("Invalid CA certificate");
+        TlsConnector::builder()
+            .add_root_certificate(ca_cert)
+            .build()
+            .expect("Failed to setup TLS")
+    });
+    let mut ws_transport = SimWsTransport::new(ws_uri, command_router, tls);
+
+    // Deliver reports to MANAGE, journaling what can't be sent
+    let mut manage_link = ManageLink::new(report_journal, controller_info);
+
+    loop {
+        manage_last_tick.store(Instant::now(), Ordering::SeqCst);
+
+        // Reconnect if needed and route incoming commands
+        ws_transport.poll();
+
+        // Send any reports
+        let next_check = Instant::now() + MANAGE_LOOP_INTERVAL;
+        manage_link.pump(&mut ws_transport, &report_channel_rx, next_check);
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::BufRead;
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+
+use guardian_core::guardian_osdp_events::local_status_report;
+use libosdp::{OsdpCardFormats, OsdpEvent, OsdpEventCardRead, OsdpEventKeyPress};
+
+use super::sim_gpio::SimInputPin;
+
+const HELP: &str = "Commands:
+  card <hex>              present a card to the reader
+  keys <digits>           type on the reader keypad, * clears and # enters
+  tamper on|off           open or close the reader tamper switch
+  door open|closed        move the door position sensor
+  rex press|release       press or release the request-to-exit button
+  limit open|closed|none  set which motorized door limit switch is active
+  help                    show this help";
+
+// The reader sends * and # as these OSDP keypad codes
+fn keypad_code(key: u8) -> u8 {
+    match key {
+        b'*' => 0x7f,
+        b'#' => 0x0d,
+        key => key,
+    }
+}
+
+// Virtual inputs the console can drive
+pub struct SimInputs {
+    pub open_limit: SimInputPin,
+    pub closed_limit: SimInputPin,
+    pub door_position: SimInputPin,
+    pub request_to_exit: SimInputPin,
+    pub limit_switch_active_high: bool,
+    pub door_position_active_high: bool,
+    pub request_to_exit_active_high: bool,
+}
+
+// Create thread reading simulator commands from stdin
+pub fn spawn_console(inputs: SimInputs, reader_event_tx: Sender<OsdpEvent>) -> JoinHandle<()> {
+    log::info!("{}", HELP);
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    log::error!("Failed to read console: {:?}", error);
+                    break;
+                }
+            };
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("card"), Some(credential)) => match hex::decode(credential) {
+                    Ok(data) => {
+                        let event = OsdpEvent::CardRead(OsdpEventCardRead {
+                            reader_no: 0,
+                            format: OsdpCardFormats::Unspecified,
+                            direction: false,
+                            nr_bits: data.len() * 8,
+                            data,
+                        });
+                        reader_event_tx.send(event).unwrap();
+                    }
+                    Err(_) => log::warn!("Card data must be hex!"),
+                },
+                (Some("keys"), Some(digits)) => {
+                    let event = OsdpEvent::KeyPress(OsdpEventKeyPress {
+                        reader_no: 0,
+                        data: digits.bytes().map(keypad_code).collect(),
+                    });
+                    reader_event_tx.send(event).unwrap();
+                }
+                (Some("tamper"), Some(state @ ("on" | "off"))) => {
+                    let event = OsdpEvent::Status(local_status_report(state == "on", false));
+                    reader_event_tx.send(event).unwrap();
+                }
+                (Some("door"), Some(position @ ("open" | "closed"))) => {
+                    let open = position == "open";
+                    let active_high = inputs.door_position_active_high;
+                    inputs.door_position.set(open == active_high);
+                }
+                (Some("rex"), Some(action @ ("press" | "release"))) => {
+                    let pressed = action == "press";
+                    let active_high = inputs.request_to_exit_active_high;
+                    inputs.request_to_exit.set(pressed == active_high);
+                }
+                (Some("limit"), Some(limit @ ("open" | "closed" | "none"))) => {
+                    let active_high = inputs.limit_switch_active_high;
+                    inputs.open_limit.set((limit == "open") == active_high);
+                    inputs.closed_limit.set((limit == "closed") == active_high);
+                }
+                (None, _) => {}
+                _ => log::info!("{}", HELP),
+            }
+        }
+    })
+}
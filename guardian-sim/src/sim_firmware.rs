@@ -0,0 +1,30 @@
+use guardian_core::guardian_firmware_update::ImageCheck;
+use guardian_core::guardian_hal::FirmwareSlots;
+
+// The simulator only runs from the build it was started from, every update is turned down
+pub struct SimFirmwareSlots;
+
+impl FirmwareSlots for SimFirmwareSlots {
+    type Error = &'static str;
+
+    fn install(&mut self, url: &str, _image_check: &mut ImageCheck) -> Result<(), &'static str> {
+        log::info!("Not installing firmware from {} in the simulator", url);
+        Err("firmware updates are not supported by the simulator")
+    }
+
+    fn is_pending_verify(&self) -> bool {
+        false
+    }
+
+    fn mark_valid(&mut self) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn rollback(&mut self) {}
+
+    fn restart(&mut self) {}
+
+    fn running_version(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+}
@@ -0,0 +1,62 @@
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use guardian_core::guardian_hal::{InputPin, OutputPin};
+
+// Virtual relay output that logs whenever it switches
+pub struct SimOutputPin {
+    name: &'static str,
+    high: bool,
+}
+
+impl SimOutputPin {
+    pub fn new(name: &'static str) -> Self {
+        Self { name, high: false }
+    }
+
+    fn set(&mut self, high: bool) {
+        if self.high != high {
+            log::info!("GPIO {}: {}", self.name, if high { "HIGH" } else { "LOW" });
+        }
+        self.high = high;
+    }
+}
+
+impl OutputPin for SimOutputPin {
+    type Error = Infallible;
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.set(true);
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.set(false);
+        Ok(())
+    }
+}
+
+// Virtual input, driven from the simulator console
+#[derive(Clone)]
+pub struct SimInputPin {
+    high: Arc<AtomicBool>,
+}
+
+impl SimInputPin {
+    pub fn new(high: bool) -> Self {
+        Self {
+            high: Arc::new(AtomicBool::new(high)),
+        }
+    }
+
+    pub fn set(&self, high: bool) {
+        self.high.store(high, Ordering::SeqCst);
+    }
+}
+
+impl InputPin for SimInputPin {
+    fn is_high(&self) -> bool {
+        self.high.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,22 @@
+use guardian_core::guardian_hal::SystemMetrics;
+use guardian_core::guardian_telemetry::NetworkInfo;
+
+// The host has no heap or link worth reporting, only the figures the core tracks are real
+pub struct SimSystemMetrics;
+
+impl SystemMetrics for SimSystemMetrics {
+    fn free_heap(&self) -> u32 {
+        0
+    }
+
+    fn min_free_heap(&self) -> u32 {
+        0
+    }
+
+    fn network(&self) -> NetworkInfo {
+        NetworkInfo {
+            link_up: true,
+            ip: None,
+        }
+    }
+}
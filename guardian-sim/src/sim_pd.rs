@@ -0,0 +1,50 @@
+use std::sync::mpsc::Receiver;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use libosdp::{
+    Channel, OsdpEvent, OsdpFlag, PdCapEntity, PdCapability, PdInfoBuilder, PeripheralDevice,
+};
+
+const PD_REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+
+// Create thread running a simulated OSDP reader on the other end of the pipe
+pub fn spawn_peripheral_device(
+    channel: Box<dyn Channel>,
+    event_rx: Receiver<OsdpEvent>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        // Prepare Peripheral Device Info, accepting a new key like a factory fresh reader. LibOSDP
+        // reads the capabilities as a C array, so the list must not be empty
+        let pd_info = PdInfoBuilder::new()
+            .flag(OsdpFlag::InstallMode)
+            .capability(PdCapability::CommunicationSecurity(PdCapEntity::new(1, 1)))
+            .capability(PdCapability::AudibleOutput(PdCapEntity::new(1, 1)))
+            .capability(PdCapability::LedControl(PdCapEntity::new(1, 1)))
+            .channel(channel)
+            .build();
+
+        // Initialize OSDP Peripheral Device
+        let mut pd =
+            PeripheralDevice::new(pd_info).expect("Failed to initialize Peripheral Device");
+        pd.set_command_callback(|command| {
+            log::info!("Simulated Reader Command: {:?}", command);
+            0
+        });
+        log::info!("Simulated OSDP Reader Initialized");
+
+        loop {
+            // Refresh Peripheral Device state
+            pd.refresh();
+
+            // Forward events typed into the console
+            while let Ok(event) = event_rx.try_recv() {
+                if let Err(error) = pd.notify_event(event) {
+                    log::error!("Failed to queue simulated reader event: {:?}", error);
+                }
+            }
+
+            thread::sleep(PD_REFRESH_INTERVAL);
+        }
+    })
+}
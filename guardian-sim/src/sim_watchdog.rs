@@ -0,0 +1,21 @@
+use std::process;
+
+use guardian_core::guardian_hal::SystemWatchdog;
+
+// The simulator has no task watchdog, restarting a wedged simulator ends it
+pub struct SimSystemWatchdog;
+
+impl SystemWatchdog for SimSystemWatchdog {
+    fn subscribe(&mut self) {}
+
+    fn feed(&mut self) {}
+
+    fn reset_reason(&self) -> String {
+        "power_on".to_string()
+    }
+
+    fn reboot(&mut self) {
+        log::error!("Exiting the simulator instead of restarting");
+        process::exit(1);
+    }
+}
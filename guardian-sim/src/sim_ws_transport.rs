@@ -0,0 +1,155 @@
+use std::net::TcpStream;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use guardian_core::guardian_command_router::CommandRouter;
+use guardian_core::guardian_global_status::{MANAGE_CONNECTED, MANAGE_RECONNECTS};
+use guardian_core::guardian_hal::Transport;
+use native_tls::TlsConnector;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http::Uri;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Connector, Message, WebSocket};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+// MANAGE websocket client for the simulator
+pub struct SimWsTransport {
+    uri: String,
+    router: Arc<CommandRouter>,
+    tls: Option<TlsConnector>,
+    socket: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
+    next_connect: Instant,
+}
+
+impl SimWsTransport {
+    // A TLS connector is only needed to trust a self-signed MANAGE certificate
+    pub fn new(uri: String, router: Arc<CommandRouter>, tls: Option<TlsConnector>) -> Self {
+        Self {
+            uri,
+            router,
+            tls,
+            socket: None,
+            next_connect: Instant::now(),
+        }
+    }
+
+    // Connect if needed, then hand any incoming commands to the router
+    pub fn poll(&mut self) {
+        let Some(socket) = self.socket.as_mut() else {
+            self.connect();
+            return;
+        };
+
+        loop {
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    log::debug!("WebSocket event: Text: {:?}", text);
+                    match serde_json::from_str(&text) {
+                        Ok(command) => {
+                            self.router.route(command);
+                        }
+                        Err(_) => {
+                            log::error!("Failed to decode incoming command!");
+                        }
+                    }
+                }
+                Ok(Message::Close(_)) => {
+                    log::warn!("Connection to MANAGE closed!");
+                    self.disconnect();
+                    return;
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(error))
+                    if error.kind() == std::io::ErrorKind::WouldBlock
+                        || error.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    return;
+                }
+                Err(error) => {
+                    log::warn!("Disconnected from MANAGE! ({:?})", error);
+                    self.disconnect();
+                    return;
+                }
+            }
+        }
+    }
+
+    fn connect(&mut self) {
+        if Instant::now() < self.next_connect {
+            return;
+        }
+        self.next_connect = Instant::now() + RECONNECT_INTERVAL;
+
+        // Resolve the MANAGE host ourselves so a custom TLS connector can be used
+        let uri: Uri = self.uri.parse().expect("Invalid MANAGE URI");
+        let host = uri.host().unwrap_or_default();
+        let port = match uri.port_u16() {
+            Some(port) => port,
+            None if uri.scheme_str() == Some("wss") => 443,
+            None => 80,
+        };
+        let stream = match TcpStream::connect((host, port)) {
+            Ok(stream) => stream,
+            Err(error) => {
+                log::warn!("Failed to connect to MANAGE at {}: {:?}", self.uri, error);
+                return;
+            }
+        };
+
+        let connector = self.tls.clone().map(Connector::NativeTls);
+        match tungstenite::client_tls_with_config(self.uri.as_str(), stream, None, connector) {
+            Ok((socket, _)) => {
+                // Poll reads so the loop can keep delivering reports
+                let read_timeout = match socket.get_ref() {
+                    MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(READ_TIMEOUT)),
+                    MaybeTlsStream::NativeTls(stream) => {
+                        stream.get_ref().set_read_timeout(Some(READ_TIMEOUT))
+                    }
+                    _ => Ok(()),
+                };
+                read_timeout.unwrap();
+
+                // MANAGE_CONNECTED is only set once the auth handshake completes
+                log::info!("Connected to MANAGE, awaiting challenge...");
+                self.socket = Some(socket);
+            }
+            Err(HandshakeError::Failure(error)) => {
+                log::warn!("Failed to connect to MANAGE at {}: {:?}", self.uri, error);
+            }
+            // The stream is still blocking during the handshake
+            Err(HandshakeError::Interrupted(_)) => unreachable!(),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.socket = None;
+        MANAGE_RECONNECTS.fetch_add(1, Ordering::SeqCst);
+        MANAGE_CONNECTED.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Transport for SimWsTransport {
+    type Error = tungstenite::Error;
+
+    fn is_open(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.socket.is_some() && MANAGE_CONNECTED.load(Ordering::SeqCst)
+    }
+
+    fn send_text(&mut self, text: &str) -> Result<(), tungstenite::Error> {
+        let Some(socket) = self.socket.as_mut() else {
+            return Err(tungstenite::Error::AlreadyClosed);
+        };
+        let result = socket.send(Message::Text(text.to_string()));
+        if result.is_err() {
+            self.disconnect();
+        }
+        result
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::Write;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use guardian_core::manage_command::{CommandOutcome, MANAGECommand, MANAGEReport};
+use guardian_mock_manage::manage_mock_server::{MockManage, ScriptStep, DEV_DEVICE_SECRET};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Simulator process, killed when the test ends
+struct Simulator {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+impl Simulator {
+    fn start(manage: &MockManage) -> Self {
+        Self::start_with(manage, &[])
+    }
+
+    fn start_with(manage: &MockManage, args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_guardian-sim"))
+            .args(["--manage", &manage.ws_base_uri(), "--mac", "02005e0000aa"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        Self { child, stdin }
+    }
+
+    fn console(&mut self, line: &str) {
+        writeln!(self.stdin, "{}", line).unwrap();
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn dev_secret() -> Vec<u8> {
+    DEV_DEVICE_SECRET.to_vec()
+}
+
+#[test]
+fn forced_open_door_is_reported() {
+    let manage = MockManage::start("127.0.0.1:0", None, Vec::new(), dev_secret()).unwrap();
+    let mut simulator = Simulator::start(&manage);
+    assert_eq!(
+        manage.wait_for_connection(TIMEOUT).as_deref(),
+        Some("02005e0000aa")
+    );
+
+    simulator.console("door open");
+    let forced_open = manage.wait_for_report(TIMEOUT, |report| {
+        matches!(report, MANAGEReport::DoorForcedOpen { door_id: 0 })
+    });
+    assert_eq!(forced_open.unwrap().mac_address, "02005e0000aa");
+}
+
+#[test]
+fn scripted_unlock_authorizes_opening() {
+    let script = vec![ScriptStep {
+        delay_ms: 0,
+        command: MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        },
+    }];
+    let manage = MockManage::start("127.0.0.1:0", None, script, dev_secret()).unwrap();
+    let mut simulator = Simulator::start(&manage);
+    assert!(manage.wait_for_connection(TIMEOUT).is_some());
+
+    // The unlock is only reported once the relay has been driven
+    let unlocked = manage.wait_for_report(TIMEOUT, |report| {
+        matches!(
+            report,
+            MANAGEReport::CommandResult {
+                result: CommandOutcome::Success,
+                ..
+            }
+        )
+    });
+    assert!(matches!(
+        unlocked.unwrap().report,
+        MANAGEReport::CommandResult { request_id, .. } if request_id == "02005e0000aa-1"
+    ));
+    simulator.console("door open");
+    assert!(manage
+        .wait_for_report(TIMEOUT, |report| matches!(
+            report,
+            MANAGEReport::DoorOpened { door_id: 0 }
+        ))
+        .is_some());
+    simulator.console("door closed");
+    assert!(manage
+        .wait_for_report(TIMEOUT, |report| matches!(
+            report,
+            MANAGEReport::DoorClosed { door_id: 0 }
+        ))
+        .is_some());
+
+    assert!(!manage.reports().iter().any(|received| matches!(
+        received.original(),
+        MANAGEReport::DoorForcedOpen { door_id: 0 }
+    )));
+}
+
+#[test]
+fn door_with_the_wrong_secret_is_not_connected() {
+    let script = vec![ScriptStep {
+        delay_ms: 0,
+        command: MANAGECommand::DoorUnlock {
+            door_id: 0,
+            duration: 5,
+        },
+    }];
+    let manage = MockManage::start("127.0.0.1:0", None, script, dev_secret()).unwrap();
+    let mut simulator = Simulator::start_with(&manage, &["--device-secret", &"00".repeat(32)]);
+    assert!(manage.wait_for_connection(Duration::from_secs(3)).is_none());
+
+    // Never unlocked, so opening the door is forced and nothing is reported
+    simulator.console("door open");
+    thread::sleep(Duration::from_millis(500));
+    assert!(manage.reports().is_empty());
+}
@@ -2,12 +2,15 @@
 set -euo pipefail
 
 # Check for the binary argument
-if [ -z "$1" ]
+if [ -z "${1:-}" ]
   then
     echo "No binary argument supplied"
+    exit 1
 fi
 
-# Copy the binary to the remote device
+# Copy the binary with the bootloader and partition table it was built with to the remote device
 scp "$1" remote-engineering:/tmp/aperture2
+scp "$(dirname "$1")/bootloader.bin" remote-engineering:/tmp/aperture2-bootloader.bin
+scp partitions.csv remote-engineering:/tmp/aperture2-partitions.csv
 #ssh remote-engineering "/home/pi/.cargo/bin/espflash flash --monitor /tmp/aperture2"
-ssh remote-engineering  -t 'bash -l -c "espflash flash --monitor /tmp/aperture2"'
\ No newline at end of file
+ssh remote-engineering  -t 'bash -l -c "espflash flash --monitor --bootloader /tmp/aperture2-bootloader.bin --partition-table /tmp/aperture2-partitions.csv --erase-parts otadata /tmp/aperture2"'
@@ -1,59 +1,105 @@
 use esp_idf_svc::hal::io::EspIOError;
-use esp_idf_svc::handle::RawHandle;
+use esp_idf_svc::sys::{EspError, ESP_ERR_INVALID_STATE};
 use esp_idf_svc::ws::client::{
     EspWebSocketClient, EspWebSocketClientConfig, WebSocketEvent, WebSocketEventType,
 };
+use esp_idf_svc::ws::FrameType;
+use guardian_core::guardian_command_router::CommandRouter;
+use guardian_core::guardian_global_status::{MANAGE_CONNECTED, MANAGE_RECONNECTS};
+use guardian_core::guardian_hal::Transport;
+use guardian_core::guardian_manage_connection::{ConnectionAction, ManageConnection};
 use hex::encode;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use super::esp_hw::get_mac_address;
-use super::manage_command::MANAGECommand;
 
-// Shared flag to indicate connection status
-pub static WS_OPEN: AtomicBool = AtomicBool::new(false);
+// Socket of the current client is up, but the MANAGE session may not be authenticated yet
+static WS_CONNECTED: AtomicBool = AtomicBool::new(false);
 
-pub fn ws_client_setup(
-    ws_base_uri: &str,
-    ws_timeout: Duration,
-    tx: Sender<MANAGECommand>,
-) -> EspWebSocketClient {
-    // Combine the WebSocket base URI with the MAC address
-    let mac_address = get_mac_address().unwrap();
-    let ws_uri = format!("{}{}/", ws_base_uri, encode(mac_address));
+// MANAGE websocket client that is recreated with backoff whenever its socket goes down
+pub struct ManageWsClient {
+    uri: String,
+    timeout: Duration,
+    router: Arc<CommandRouter>,
+    client: Option<EspWebSocketClient<'static>>,
+    connection: ManageConnection,
+}
+
+impl ManageWsClient {
+    pub fn new(ws_base_uri: &str, ws_timeout: Duration, router: Arc<CommandRouter>) -> Self {
+        // Combine the WebSocket base URI with the MAC address
+        let mac_address = get_mac_address().unwrap();
+        Self {
+            uri: format!("{}{}/", ws_base_uri, encode(mac_address)),
+            timeout: ws_timeout,
+            router,
+            client: None,
+            connection: ManageConnection::new(Instant::now()),
+        }
+    }
 
-    // Configure the WebSocket client
-    let config = EspWebSocketClientConfig {
-        crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
-        ..Default::default()
-    };
+    // Create or tear down the client as the connection state says
+    pub fn poll(&mut self) {
+        let open = self.client.is_some() && WS_CONNECTED.load(Ordering::SeqCst);
+        let authenticated = open && MANAGE_CONNECTED.load(Ordering::SeqCst);
+        match self.connection.update(open, authenticated, Instant::now()) {
+            ConnectionAction::Connect => {
+                log::info!("Connecting to MANAGE at {}", self.uri);
+                self.client = self.connect();
+            }
+            ConnectionAction::Teardown => {
+                // Dropping the client stops its task and frees the event callback
+                self.client = None;
+                WS_CONNECTED.store(false, Ordering::SeqCst);
+                MANAGE_CONNECTED.store(false, Ordering::SeqCst);
+                MANAGE_RECONNECTS.fetch_add(1, Ordering::SeqCst);
+            }
+            ConnectionAction::Wait => {}
+        }
+    }
 
-    // Create the WebSocket client
-    let ws_client = EspWebSocketClient::new(&ws_uri, &config, ws_timeout, move |event| {
-        on_websocket_event(&tx, event)
-    });
+    fn connect(&self) -> Option<EspWebSocketClient<'static>> {
+        // Reconnects are left to poll(), so the client gives up once its socket is down
+        let config = EspWebSocketClientConfig {
+            crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+            disable_auto_reconnect: true,
+            ..Default::default()
+        };
 
-    // Assume WS is open until something goes wrong
-    WS_OPEN.store(true, Ordering::SeqCst);
-    ws_client.unwrap()
+        let router = self.router.clone();
+        match EspWebSocketClient::new(&self.uri, &config, self.timeout, move |event| {
+            on_websocket_event(&router, event)
+        }) {
+            Ok(client) => Some(client),
+            Err(error) => {
+                log::error!("Failed to create WebSocket client: {:?}", error);
+                None
+            }
+        }
+    }
 }
 
-fn on_websocket_event(tx: &Sender<MANAGECommand>, event: &Result<WebSocketEvent, EspIOError>) {
+fn on_websocket_event(router: &CommandRouter, event: &Result<WebSocketEvent, EspIOError>) {
     match event {
         Ok(event) => {
             match event.event_type {
                 WebSocketEventType::Connected => {
-                    log::info!("Connected to MANAGE!");
+                    // MANAGE_CONNECTED is only set once the auth handshake completes
+                    log::info!("Connected to MANAGE, awaiting challenge...");
+                    WS_CONNECTED.store(true, Ordering::SeqCst);
                 }
                 WebSocketEventType::Disconnected => {
                     log::warn!("Disconnected from MANAGE!");
+                    WS_CONNECTED.store(false, Ordering::SeqCst);
+                    MANAGE_CONNECTED.store(false, Ordering::SeqCst);
                 }
                 WebSocketEventType::Text(data) => {
                     log::debug!("WebSocket event: Text: {:?}", data);
                     match serde_json::from_str(data) {
                         Ok(command) => {
-                            tx.send(command).unwrap();
+                            router.route(command);
                         }
                         Err(_) => {
                             log::error!("Failed to decode incoming command!");
@@ -61,8 +107,9 @@ fn on_websocket_event(tx: &Sender<MANAGECommand>, event: &Result<WebSocketEvent,
                     }
                 }
                 WebSocketEventType::Closed => {
-                    log::warn!("Connection to MANAGE closed! Marking for retry...");
-                    WS_OPEN.store(false, Ordering::SeqCst);
+                    log::warn!("Connection to MANAGE closed!");
+                    WS_CONNECTED.store(false, Ordering::SeqCst);
+                    MANAGE_CONNECTED.store(false, Ordering::SeqCst);
                 }
                 // Any other event type
                 _ => {
@@ -76,12 +123,22 @@ fn on_websocket_event(tx: &Sender<MANAGECommand>, event: &Result<WebSocketEvent,
     }
 }
 
-pub fn nuke_ws_client(ws_client: &EspWebSocketClient) {
-    // Retrieve the WebSocket client handle
-    let ws_client_handle = ws_client.handle();
+// Reports are handed to the current client, and refused while there is none
+impl Transport for ManageWsClient {
+    type Error = EspError;
+
+    fn is_open(&self) -> bool {
+        self.client.is_some() && WS_CONNECTED.load(Ordering::SeqCst)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.is_open() && MANAGE_CONNECTED.load(Ordering::SeqCst)
+    }
 
-    // Destroy the WebSocket client
-    unsafe {
-        esp_idf_svc::sys::esp_websocket_client_destroy(ws_client_handle);
+    fn send_text(&mut self, text: &str) -> Result<(), EspError> {
+        match self.client.as_mut() {
+            Some(client) => client.send(FrameType::Text(false), text.as_bytes()),
+            None => Err(EspError::from_infallible::<ESP_ERR_INVALID_STATE>()),
+        }
     }
 }
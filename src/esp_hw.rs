@@ -1,4 +1,15 @@
-use esp_idf_svc::sys::{esp_efuse_mac_get_default, ESP_OK};
+use std::ffi::CStr;
+
+use esp_idf_svc::eth::{BlockingEth, EspEth, EthDriver, RmiiEth};
+use esp_idf_svc::hal::gpio::{AnyInputPin, AnyOutputPin, Input, Output, PinDriver};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::{
+    esp_efuse_mac_get_default, esp_get_free_heap_size, esp_get_idf_version,
+    esp_get_minimum_free_heap_size, EspError, ESP_OK,
+};
+use guardian_core::guardian_hal::{InputPin, OutputPin, SystemMetrics};
+use guardian_core::guardian_storage::KeyValueStore;
+use guardian_core::guardian_telemetry::NetworkInfo;
 
 pub fn get_mac_address() -> Result<[u8; 6], &'static str> {
     let mut mac: [u8; 6] = [0; 6];
@@ -10,3 +21,103 @@ pub fn get_mac_address() -> Result<[u8; 6], &'static str> {
         Err("Failed to get MAC address")
     }
 }
+
+pub fn idf_version() -> String {
+    unsafe { CStr::from_ptr(esp_get_idf_version()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Heap and Ethernet figures for the telemetry report
+pub struct EspSystemMetrics {
+    pub eth: BlockingEth<EspEth<'static, EthDriver<'static, RmiiEth>>>,
+}
+
+impl SystemMetrics for EspSystemMetrics {
+    fn free_heap(&self) -> u32 {
+        unsafe { esp_get_free_heap_size() }
+    }
+
+    fn min_free_heap(&self) -> u32 {
+        unsafe { esp_get_minimum_free_heap_size() }
+    }
+
+    fn network(&self) -> NetworkInfo {
+        let netif = self.eth.eth().netif();
+        NetworkInfo {
+            link_up: netif.is_up().unwrap_or(false),
+            ip: netif
+                .get_ip_info()
+                .ok()
+                .filter(|ip_info| !ip_info.ip.is_unspecified())
+                .map(|ip_info| ip_info.ip.to_string()),
+        }
+    }
+}
+
+// GPIOs are assigned at runtime from GuardianConfig, which rejects pins used by Ethernet,
+// flash or the console and pins assigned twice, so each pin is only claimed once
+pub fn output_pin(pin: i32) -> AnyOutputPin {
+    unsafe { AnyOutputPin::new(pin) }
+}
+
+pub fn input_pin(pin: i32) -> AnyInputPin {
+    unsafe { AnyInputPin::new(pin) }
+}
+
+// GPIO output driven by the door security logic
+pub struct EspOutputPin<'d>(pub PinDriver<'d, AnyOutputPin, Output>);
+
+impl OutputPin for EspOutputPin<'_> {
+    type Error = EspError;
+
+    fn set_high(&mut self) -> Result<(), EspError> {
+        self.0.set_high()
+    }
+
+    fn set_low(&mut self) -> Result<(), EspError> {
+        self.0.set_low()
+    }
+}
+
+// GPIO input read by the door security logic
+pub struct EspInputPin<'d>(pub PinDriver<'d, AnyInputPin, Input>);
+
+impl InputPin for EspInputPin<'_> {
+    fn is_high(&self) -> bool {
+        self.0.is_high()
+    }
+}
+
+// Blob storage in a single NVS namespace
+pub struct EspNvsStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl EspNvsStore {
+    pub fn new(nvs_partition: EspDefaultNvsPartition, namespace: &str) -> Result<Self, EspError> {
+        let nvs = EspNvs::new(nvs_partition, namespace, true)?;
+        Ok(Self { nvs })
+    }
+}
+
+impl KeyValueStore for EspNvsStore {
+    type Error = EspError;
+
+    fn get_blob(&self, key: &str) -> Result<Option<Vec<u8>>, EspError> {
+        // Size the buffer to the stored blob
+        let Some(blob_len) = self.nvs.blob_len(key)? else {
+            return Ok(None);
+        };
+        let mut buf = vec![0u8; blob_len];
+        Ok(self.nvs.get_blob(key, &mut buf)?.map(|data| data.to_vec()))
+    }
+
+    fn set_blob(&mut self, key: &str, data: &[u8]) -> Result<(), EspError> {
+        self.nvs.set_blob(key, data)
+    }
+
+    fn remove_blob(&mut self, key: &str) -> Result<(), EspError> {
+        self.nvs.remove(key).map(|_| ())
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use esp_idf_svc::hal::reset;
+use esp_idf_svc::http::client::{Configuration, EspHttpConnection};
+use esp_idf_svc::http::Method;
+use esp_idf_svc::ota::{EspOta, SlotState};
+use guardian_core::guardian_firmware_update::ImageCheck;
+use guardian_core::guardian_hal::FirmwareSlots;
+
+const DOWNLOAD_CHUNK_LEN: usize = 4096;
+
+// Every blocking HTTP call gives up well before the watchdog considers the thread stalled
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The two OTA app partitions, with rollback handled by the bootloader
+pub struct EspFirmwareSlots {
+    ota: EspOta,
+}
+
+impl EspFirmwareSlots {
+    pub fn new() -> Result<Self, String> {
+        let ota = EspOta::new().map_err(|error| format!("{:?}", error))?;
+        Ok(Self { ota })
+    }
+}
+
+impl FirmwareSlots for EspFirmwareSlots {
+    type Error = String;
+
+    fn install(&mut self, url: &str, image_check: &mut ImageCheck) -> Result<(), String> {
+        // Fetch the image over HTTPS, checking the server against the built in CA bundle
+        let mut connection = EspHttpConnection::new(&Configuration {
+            buffer_size: Some(DOWNLOAD_CHUNK_LEN),
+            timeout: Some(HTTP_TIMEOUT),
+            crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+            ..Default::default()
+        })
+        .map_err(|error| format!("HTTP client: {:?}", error))?;
+        image_check.keep_alive();
+        connection
+            .initiate_request(Method::Get, url, &[])
+            .map_err(|error| format!("HTTP request: {:?}", error))?;
+        image_check.keep_alive();
+        connection
+            .initiate_response()
+            .map_err(|error| format!("HTTP response: {:?}", error))?;
+        image_check.keep_alive();
+        if connection.status() != 200 {
+            return Err(format!("HTTP status {}", connection.status()));
+        }
+
+        // Write the inactive slot chunk by chunk, dropping it on any error
+        let mut update = self
+            .ota
+            .initiate_update()
+            .map_err(|error| format!("OTA begin: {:?}", error))?;
+        // Erasing the inactive slot takes a few seconds
+        image_check.keep_alive();
+        let mut buf = [0u8; DOWNLOAD_CHUNK_LEN];
+        loop {
+            let len = match connection.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(error) => {
+                    let _ = update.abort();
+                    return Err(format!("HTTP read: {:?}", error));
+                }
+            };
+            image_check.update(&buf[..len]);
+            if let Err(error) = update.write(&buf[..len]) {
+                let _ = update.abort();
+                return Err(format!("OTA write: {:?}", error));
+            }
+        }
+
+        if !image_check.finish() {
+            let _ = update.abort();
+            return Err("sha256 mismatch".to_string());
+        }
+
+        // Boot the new slot from the next restart on
+        update
+            .complete()
+            .map_err(|error| format!("OTA complete: {:?}", error))
+    }
+
+    fn is_pending_verify(&self) -> bool {
+        self.ota
+            .get_running_slot()
+            .is_ok_and(|slot| slot.state == SlotState::Unverified)
+    }
+
+    fn mark_valid(&mut self) -> Result<(), String> {
+        self.ota
+            .mark_running_slot_valid()
+            .map_err(|error| format!("{:?}", error))
+    }
+
+    fn rollback(&mut self) {
+        let error = self.ota.mark_running_slot_invalid_and_reboot();
+        log::error!("Failed to roll back: {:?}", error);
+    }
+
+    fn restart(&mut self) {
+        reset::restart();
+    }
+
+    fn running_version(&self) -> String {
+        self.ota
+            .get_running_slot()
+            .ok()
+            .and_then(|slot| slot.firmware)
+            .map(|firmware| firmware.version.to_string())
+            .unwrap_or_default()
+    }
+}
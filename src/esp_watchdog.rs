@@ -0,0 +1,41 @@
+use std::ptr;
+
+use esp_idf_svc::hal::reset::{self, ResetReason};
+use esp_idf_svc::sys::{esp_task_wdt_add, esp_task_wdt_reset, ESP_OK};
+use guardian_core::guardian_hal::SystemWatchdog;
+
+// The ESP-IDF task watchdog, panicking and resetting the chip when the supervisor stops feeding it
+pub struct EspSystemWatchdog;
+
+impl SystemWatchdog for EspSystemWatchdog {
+    fn subscribe(&mut self) {
+        let result = unsafe { esp_task_wdt_add(ptr::null_mut()) };
+        if result != ESP_OK {
+            log::error!("Failed to subscribe to the task watchdog: {}", result);
+        }
+    }
+
+    fn feed(&mut self) {
+        unsafe { esp_task_wdt_reset() };
+    }
+
+    fn reset_reason(&self) -> String {
+        match ResetReason::get() {
+            ResetReason::PowerOn => "power_on",
+            ResetReason::ExternalPin => "external_pin",
+            ResetReason::Software => "software",
+            ResetReason::Panic => "panic",
+            ResetReason::InterruptWatchdog => "interrupt_watchdog",
+            ResetReason::TaskWatchdog => "task_watchdog",
+            ResetReason::Watchdog => "watchdog",
+            ResetReason::DeepSleep => "deep_sleep",
+            ResetReason::Brownout => "brownout",
+            _ => "unknown",
+        }
+        .to_string()
+    }
+
+    fn reboot(&mut self) {
+        reset::restart();
+    }
+}
@@ -3,44 +3,55 @@
 
 use std::sync::atomic::Ordering;
 use std::sync::mpmc::sync_channel;
-use std::sync::mpsc::{self, channel};
-use std::sync::Arc;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
-use std::{mem, thread};
 
-use aperture_door_security::DoorSecurityDoorType;
-use aperture_ws_client::nuke_ws_client;
-use atomic_time::AtomicInstant;
+use aperture_ws_client::ManageWsClient;
+use esp_hw::{EspInputPin, EspNvsStore, EspOutputPin, EspSystemMetrics};
 use esp_idf_svc::eth::EthDriver;
 use esp_idf_svc::hal::delay;
-use esp_idf_svc::hal::gpio::{Gpio0, Gpio1, Gpio16, Gpio17, InputPin, OutputPin, PinDriver};
-use esp_idf_svc::hal::uart::{config, UartDriver};
+use esp_idf_svc::hal::gpio::{Gpio0, Gpio1, Gpio16, Gpio17, PinDriver};
+use esp_idf_svc::hal::uart::{config as uart_config, UartDriver};
 use esp_idf_svc::hal::units::Hertz;
+use esp_idf_svc::sntp::EspSntp;
 use esp_idf_svc::sys::ESP_ERR_TIMEOUT;
-use esp_idf_svc::ws::FrameType;
-use guardian_global_status::PD_ONLINE;
-use libosdp::{ControlPanel, OsdpEvent, PdInfoBuilder};
-use manage_command::{MANAGECommand, MANAGEReport};
-use osdp_serial_channel::SerialChannel;
+use esp_ota::EspFirmwareSlots;
+use esp_watchdog::EspSystemWatchdog;
+use guardian_core::aperture_door_security::{
+    DoorSecurity, DoorSecurityInputs, DoorSecurityOutputs,
+};
+use guardian_core::guardian_access_cache::AccessCache;
+use guardian_core::guardian_command_router::CommandRouter;
+use guardian_core::guardian_config::ConfigStore;
+use guardian_core::guardian_device_identity::{DeviceIdentity, ManageSession};
+use guardian_core::guardian_firmware_update::FirmwareUpdater;
+use guardian_core::guardian_global_status::{MANAGE_CONNECTED, OSDP_RX_QUEUE_FULL};
+use guardian_core::guardian_hal::{SharedClock, SystemClock, SystemWatchdog};
+use guardian_core::guardian_hello::ControllerInfo;
+use guardian_core::guardian_manage_link::ManageLink;
+use guardian_core::guardian_osdp_events::OsdpEventHandler;
+use guardian_core::guardian_osdp_secure_channel::SecureChannel;
+use guardian_core::guardian_reader_feedback::ReaderFeedback;
+use guardian_core::guardian_report_journal::ReportJournal;
+use guardian_core::guardian_runtime;
+use guardian_core::guardian_storage::GuardianStorage;
+use guardian_core::guardian_telemetry::TelemetrySources;
+use guardian_core::guardian_watchdog::Watchdog;
+use guardian_core::manage_command::{CommandRequest, MANAGEReport};
+use guardian_core::osdp_serial_channel::SerialChannel;
+use guardian_core::osdp_time_patch;
+use libosdp::ControlPanel;
 
 mod aperture_core;
-mod aperture_door_security;
 mod aperture_ws_client;
 mod esp_hw;
-mod guardian_global_status;
-mod manage_command;
-mod osdp_serial_channel;
-mod osdp_time_patch;
+mod esp_ota;
+mod esp_watchdog;
 
-#[macro_use]
-extern crate lazy_static;
-
-// Core Parameters
-const WS_BASE_URI: &str = "wss://manage.netinformatik.com/ws/office-security/door-commands/";
-const WS_TIMEOUT: Duration = Duration::from_secs(10);
-const SYSTEM_HEALTH_LOOP_INTERVAL: Duration = Duration::from_secs(5);
-const DOOR_SECURITY_LOOP_INTERVAL: Duration = Duration::from_millis(100);
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+// How often the WebSocket client is checked while no reports come in
+const MANAGE_LOOP_INTERVAL: Duration = Duration::from_secs(1);
 
 fn main() {
     // It is necessary to call this function once. Otherwise some patches to the runtime
@@ -56,9 +67,11 @@ fn main() {
 
     // Report Start
     log::info!("Initializing Guardian...");
+    osdp_time_patch::start();
+    let booted_at = Instant::now();
 
     // Fetch the peripherals, event loop, and NVS partition
-    let (peripherals, sys_loop, _nvs) = aperture_core::system_setup();
+    let (peripherals, sys_loop, nvs) = aperture_core::system_setup();
 
     // Initialize Ethernet Driver
     let eth_driver = EthDriver::new_rmii(
@@ -88,16 +101,114 @@ fn main() {
     eth.start().unwrap();
     log::info!("Ethernet Driver Started");
 
-    // Setup channel for command data
-    let (command_channel_tx, command_channel_rx) = mpsc::channel::<MANAGECommand>();
-
     // Setup channel for report data
     let (report_channel_tx, report_channel_rx) = mpsc::channel::<MANAGEReport>();
 
+    // Shared monotonic clock
+    let clock: SharedClock = Arc::new(SystemClock);
+
+    // Load the site configuration from NVS
+    let config_storage = GuardianStorage::new(EspNvsStore::new(nvs.clone(), "config").unwrap());
+    let config_store = ConfigStore::load(config_storage, report_channel_tx.clone());
+    let config = config_store.running().clone();
+    let config_store = Arc::new(Mutex::new(config_store));
+
+    // Setup a command channel for each door
+    let (door_command_txs, door_command_rxs): (Vec<_>, Vec<_>) = config
+        .doors
+        .iter()
+        .map(|_| mpsc::channel::<CommandRequest>())
+        .unzip();
+
+    // Load the offline access cache from NVS
+    let access_cache_storage =
+        GuardianStorage::new(EspNvsStore::new(nvs.clone(), "access_cache").unwrap());
+    let access_cache = Arc::new(Mutex::new(AccessCache::load(access_cache_storage)));
+
+    // Load the journal of undelivered reports from NVS
+    let report_journal_storage =
+        GuardianStorage::new(EspNvsStore::new(nvs.clone(), "report_journal").unwrap());
+    let report_journal = Arc::new(Mutex::new(ReportJournal::load(
+        report_journal_storage,
+        clock.clone(),
+    )));
+
+    // Load the factory provisioned device secret used to authenticate with MANAGE
+    let identity_storage = GuardianStorage::new(EspNvsStore::new(nvs.clone(), "identity").unwrap());
+    let identity = DeviceIdentity::load(
+        &identity_storage,
+        hex::encode(esp_hw::get_mac_address().unwrap()),
+    );
+    let controller_info = ControllerInfo::new(
+        env!("CARGO_PKG_VERSION"),
+        Some(esp_hw::idf_version()),
+        EspSystemWatchdog.reset_reason(),
+        identity.mac_address.clone(),
+        &config,
+        booted_at,
+    );
+    let manage_session = Arc::new(Mutex::new(ManageSession::new(
+        identity.clone(),
+        report_channel_tx.clone(),
+        &MANAGE_CONNECTED,
+    )));
+
+    // Setup channel for reader LED, buzzer and text commands
+    let (reader_command_tx, reader_command_rx) = mpsc::channel::<CommandRequest>();
+
+    // Supervise the worker threads, reporting why the controller last restarted
+    let watchdog_storage = GuardianStorage::new(EspNvsStore::new(nvs.clone(), "watchdog").unwrap());
+    let mut watchdog = Watchdog::new(
+        EspSystemWatchdog,
+        watchdog_storage,
+        report_channel_tx.clone(),
+    );
+
+    // Handle firmware updates, confirming or rolling back a freshly installed image. A board
+    // flashed without the OTA partition table keeps running with updates disabled
+    let firmware_command_tx = match EspFirmwareSlots::new() {
+        Ok(firmware_slots) => {
+            let (firmware_command_tx, firmware_command_rx) = mpsc::channel::<CommandRequest>();
+            let firmware_storage =
+                GuardianStorage::new(EspNvsStore::new(nvs.clone(), "firmware").unwrap());
+            let firmware_updater = FirmwareUpdater::new(
+                firmware_slots,
+                identity,
+                firmware_storage,
+                report_channel_tx.clone(),
+                Instant::now(),
+            );
+            guardian_runtime::spawn_firmware_update(
+                firmware_updater,
+                firmware_command_rx,
+                report_channel_tx.clone(),
+                watchdog.register("firmware_update"),
+            );
+            Some(firmware_command_tx)
+        }
+        Err(error) => {
+            log::error!("Firmware updates disabled: {}", error);
+            None
+        }
+    };
+
+    // Setup command router for incoming MANAGE commands
+    let command_router = Arc::new(CommandRouter::new(
+        door_command_txs.clone(),
+        reader_command_tx.clone(),
+        firmware_command_tx,
+        report_channel_tx.clone(),
+        access_cache.clone(),
+        report_journal.clone(),
+        config_store,
+        manage_session,
+    ));
+
     // Initialize UART for OSDP
-    let osdp_uart_tx_pin = peripherals.pins.gpio33.downgrade_output();
-    let osdp_uart_rx_pin = peripherals.pins.gpio34.downgrade_input();
-    let osdp_uart_config = config::Config::new().baudrate(Hertz(9_600));
+    let pins = &config.pins;
+    let osdp_uart_tx_pin = esp_hw::output_pin(pins.osdp_uart_tx);
+    let osdp_uart_rx_pin = esp_hw::input_pin(pins.osdp_uart_rx);
+    let osdp_uart_config = uart_config::Config::new().baudrate(Hertz(config.osdp_baud_rate));
     let osdp_uart = UartDriver::new(
         peripherals.uart1,
         osdp_uart_tx_pin,
@@ -110,40 +221,86 @@ fn main() {
     log::info!("OSDP UART Initialized");
 
     // Setup MAX485 REDE Pin
-    let osdp_max485_rede_output = peripherals.pins.gpio14.downgrade_output();
+    let osdp_max485_rede_output = esp_hw::output_pin(pins.osdp_max485_rede);
     let mut osdp_max485_rede = PinDriver::output(osdp_max485_rede_output).unwrap();
     log::info!("OSDP MAX485 REDE Pin Initialized");
 
-    // Initialize Open, Close, Stop/Unlock Pins
-    let stop_unlock_pin_output = peripherals.pins.gpio13.downgrade_output();
-    let stop_unlock_pin = PinDriver::output(stop_unlock_pin_output).unwrap();
-    let open_pin_output = peripherals.pins.gpio32.downgrade_output();
-    let open_pin = PinDriver::output(open_pin_output).unwrap();
-    let close_pin_output = peripherals.pins.gpio4.downgrade_output();
-    let close_pin = PinDriver::output(close_pin_output).unwrap();
-
-    // Initialize the door security handler
-    let mut door_security = aperture_door_security::DoorSecurity::new(
-        DoorSecurityDoorType::LockFailSecure,
-        open_pin,
-        close_pin,
-        stop_unlock_pin,
-    );
+    // Initialize the pins and security handler of each door
+    let mut door_securities = Vec::new();
+    for (door_id, door) in config.doors.iter().enumerate() {
+        let pins = &door.pins;
+
+        // Initialize Open, Close, Stop/Unlock Pins
+        let stop_unlock_pin_output = esp_hw::output_pin(pins.stop_unlock);
+        let stop_unlock_pin = EspOutputPin(PinDriver::output(stop_unlock_pin_output).unwrap());
+        let open_pin_output = esp_hw::output_pin(pins.open);
+        let open_pin = EspOutputPin(PinDriver::output(open_pin_output).unwrap());
+        let close_pin_output = esp_hw::output_pin(pins.close);
+        let close_pin = EspOutputPin(PinDriver::output(close_pin_output).unwrap());
+
+        // Initialize Open/Closed Limit Switch Pins
+        let (open_limit_pin, closed_limit_pin) = if door.limit_switches_fitted {
+            (
+                Some(EspInputPin(
+                    PinDriver::input(esp_hw::input_pin(pins.open_limit)).unwrap(),
+                )),
+                Some(EspInputPin(
+                    PinDriver::input(esp_hw::input_pin(pins.closed_limit)).unwrap(),
+                )),
+            )
+        } else {
+            (None, None)
+        };
+
+        // Initialize Door Position Sensor & Request-to-Exit Pins
+        let door_position_pin = if door.door_position_sensor_fitted {
+            Some(EspInputPin(
+                PinDriver::input(esp_hw::input_pin(pins.door_position)).unwrap(),
+            ))
+        } else {
+            None
+        };
+        let request_to_exit_pin = if door.request_to_exit_fitted {
+            Some(EspInputPin(
+                PinDriver::input(esp_hw::input_pin(pins.request_to_exit)).unwrap(),
+            ))
+        } else {
+            None
+        };
+
+        // Initialize the door security handler
+        door_securities.push(DoorSecurity::new(
+            door_id as u32,
+            door.door_type(),
+            DoorSecurityOutputs {
+                open: open_pin,
+                close: close_pin,
+                stop_unlock: stop_unlock_pin,
+            },
+            DoorSecurityInputs {
+                open_limit: open_limit_pin,
+                closed_limit: closed_limit_pin,
+                door_position: door_position_pin,
+                request_to_exit: request_to_exit_pin,
+            },
+            door.door_input_config(),
+            ReaderFeedback::new(
+                config.reader_feedback.clone(),
+                config.door_readers(door_id as u32),
+                reader_command_tx.clone(),
+            ),
+            report_channel_tx.clone(),
+            clock.clone(),
+        ));
+    }
     log::info!("Door Security Pin Handler System Initialized");
 
     // Initialize OSDP Serial TX & RX MPMC Queues
     let (osdp_serial_tx_sender, osdp_serial_tx_receiver) = sync_channel::<u8>(256);
     let (osdp_serial_rx_sender, osdp_serial_rx_receiver) = sync_channel::<u8>(256);
+    let osdp_serial_tx_queue = osdp_serial_tx_receiver.clone();
     log::info!("OSDP Serial MPMC Queues Initialized");
 
-    // Setup Serial Port
-    let serial_channel = Box::new(SerialChannel::new(
-        1,
-        osdp_serial_tx_sender,
-        osdp_serial_rx_receiver,
-    ));
-    log::info!("OSDP Serial Channel Initialized");
-
     // Split the UART into TX and RX
     let (mut osdp_uart_tx, osdp_uart_rx) = osdp_uart.into_split();
 
@@ -163,6 +320,7 @@ fn main() {
                         Err(error) => match error {
                             std::sync::mpmc::TrySendError::Full(_) => {
                                 log::warn!("WARNING: OSDP Serial RX Queue Full!");
+                                OSDP_RX_QUEUE_FULL.fetch_add(1, Ordering::SeqCst);
                             }
                             std::sync::mpmc::TrySendError::Disconnected(_) => {
                                 log::error!("ERROR: OSDP Serial RX Queue Disconnected!");
@@ -213,183 +371,126 @@ fn main() {
 
     log::info!("OSDP Serial Thread Initialized");
 
-    // Prepare Peripheral Device(s) Info
-    let mut initial_pd_builder = PdInfoBuilder::new();
-    initial_pd_builder = initial_pd_builder.channel(serial_channel);
-    let pd_info = initial_pd_builder.build();
-    let pd_infos = vec![pd_info];
+    // Load each reader's secure channel key from NVS
+    let osdp_key_storage = Arc::new(Mutex::new(GuardianStorage::new(
+        EspNvsStore::new(nvs.clone(), "osdp_keys").unwrap(),
+    )));
+    let secure_channels: Vec<SecureChannel> = (0..config.readers.len() as i32)
+        .map(|reader| {
+            SecureChannel::load(
+                reader,
+                config.osdp_secure_channel,
+                osdp_key_storage.clone(),
+                report_channel_tx.clone(),
+            )
+        })
+        .collect();
+
+    // Prepare Peripheral Device(s) Info, every reader shares the serial port
+    let pd_infos = guardian_runtime::reader_pd_infos(
+        &config.readers,
+        config.osdp_baud_rate,
+        &secure_channels,
+        || {
+            Box::new(SerialChannel::new(
+                1,
+                osdp_serial_tx_sender.clone(),
+                osdp_serial_rx_receiver.clone(),
+            ))
+        },
+    );
+    log::info!("OSDP Serial Channel Initialized");
 
     // Initialize OSDP Control Panel
-    let mut cp = ControlPanel::new(pd_infos).expect("Failed to initialize Control Panel");
+    let cp = ControlPanel::new(pd_infos).expect("Failed to initialize Control Panel");
     log::info!("OSDP Control Panel Initialized");
 
-    // Initialize a channel for processing events
-    let (event_tx, event_rx) = channel::<OsdpEvent>();
-
-    // Setup Event Handler
-    cp.set_event_callback(move |_pd, event| {
-        // Send Event to Event Handler
-        event_tx.send(event).expect("Failed to send event");
-
-        // Report Back Successful Event Handling
-        return 0;
-    });
-    log::info!("OSDP Event Handler Initialized");
-
-    // Initialize Loop Timer
-    let mut next_refresh = Instant::now() + Duration::from_millis(50);
-
     // Report Ready
     log::info!("Guardian Local System Initialization Complete!");
 
     // Create thread to handle OSDP CP events & other tasks
-    let osdp_event_report_channel_tx = report_channel_tx.clone();
-    thread::spawn(move || {
-        // Loop and wait for events
-        loop {
-            // Refresh Control Panel state
-            cp.refresh();
-
-            // Check for events
-            while let Ok(event) = event_rx.try_recv() {
-                // Process Event
-                match event {
-                    libosdp::OsdpEvent::CardRead(card_read_event) => {
-                        log::info!("Card Read: {:?}", card_read_event);
-                        let report = MANAGEReport::OsdpCardRead {
-                            event: card_read_event,
-                        };
-                        osdp_event_report_channel_tx.send(report).unwrap();
-                    }
-                    libosdp::OsdpEvent::KeyPress(key_press_event) => {
-                        log::info!("Key Press: {:?}", key_press_event);
-                        let report = MANAGEReport::OsdpKeyPress {
-                            event: key_press_event,
-                        };
-                        osdp_event_report_channel_tx.send(report).unwrap();
-                    }
-                    _ => {
-                        log::info!("Event: {:?}", event);
-                    }
-                }
-            }
-
-            // Print Info
-            PD_ONLINE.store(cp.is_online(0), Ordering::SeqCst);
-
-            // Sleep for ~50ms
-            thread::sleep(next_refresh.saturating_duration_since(Instant::now()));
-
-            // Update next refresh time
-            next_refresh += Duration::from_millis(50);
-        }
-    });
-
-    // Initialize the door security last tick time
-    let door_security_last_tick = Arc::new(AtomicInstant::now());
-    let last_tick = Arc::clone(&door_security_last_tick);
+    let osdp_event_handler = OsdpEventHandler::new(
+        access_cache,
+        report_channel_tx.clone(),
+        door_command_txs,
+        config.readers.clone(),
+        &MANAGE_CONNECTED,
+    );
+    guardian_runtime::spawn_osdp_control_panel(
+        cp,
+        osdp_event_handler,
+        secure_channels,
+        reader_command_rx,
+        report_channel_tx.clone(),
+        watchdog.register("osdp"),
+    );
 
-    // Create thread to handle door system
-    thread::spawn(move || {
-        loop {
-            match command_channel_rx.try_recv() {
-                Ok(command) => {
-                    // We received a command, handle it
-                    door_security.handle_command(command);
-                }
-                Err(_) => {
-                    // Tick the door security system and sleep for a while
-                    door_security.tick();
-                    door_security_last_tick.store(Instant::now(), Ordering::SeqCst);
-                    thread::sleep(DOOR_SECURITY_LOOP_INTERVAL);
-                }
-            }
-        }
-    });
+    // Create a thread to handle each door, tracking when it last ticked and its state
+    let mut door_security_last_ticks = Vec::new();
+    let mut door_statuses = Vec::new();
+    for (door_id, (door_security, door_command_rx)) in door_securities
+        .into_iter()
+        .zip(door_command_rxs)
+        .enumerate()
+    {
+        let door_security_last_tick = watchdog.register(&format!("door_{}", door_id));
+        let door_status = Arc::new(Mutex::new(door_security.status()));
+        guardian_runtime::spawn_door_security(
+            door_security,
+            door_command_rx,
+            door_security_last_tick.clone(),
+            door_status.clone(),
+        );
+        door_security_last_ticks.push(door_security_last_tick);
+        door_statuses.push(door_status);
+    }
 
-    // Initialize Heartbeat
-    let mut next_heartbeat = Instant::now();
+    // Create thread to handle system health and telemetry
+    let manage_last_tick = watchdog.register("manage");
+    let system_health_last_tick = watchdog.register("system_health");
+    let telemetry = TelemetrySources {
+        workers: watchdog.worker_ticks(),
+        doors: door_statuses,
+        osdp_queues: vec![
+            ("tx".to_string(), osdp_serial_tx_queue),
+            ("rx".to_string(), osdp_serial_rx_receiver.clone()),
+        ],
+    };
+    guardian_runtime::spawn_system_health(
+        door_security_last_ticks,
+        report_channel_tx.clone(),
+        config.heartbeat_interval(),
+        config.readers.len(),
+        EspSystemMetrics { eth },
+        telemetry,
+        system_health_last_tick,
+    );
 
-    // Create thread to handle system health
-    thread::spawn(move || {
-        loop {
-            // Sleep for a while
-            thread::sleep(SYSTEM_HEALTH_LOOP_INTERVAL);
+    // Create thread to restart the controller if any of the threads above wedges
+    guardian_runtime::spawn_watchdog(watchdog);
 
-            // Retrieve elapsed time
-            let elapsed = last_tick.load(Ordering::SeqCst).elapsed();
+    // Sync the wall clock so stale signed commands can be rejected
+    let _sntp = EspSntp::new_default().unwrap();
 
-            // Display System Status
-            let status_ip_info: String;
-            match eth.eth().netif().get_ip_info() {
-                Ok(ip_info) => {
-                    status_ip_info = format!("IP: {}\n", ip_info.ip);
-                }
-                Err(error) => {
-                    status_ip_info = format!("IP: Not Available! ({:?})\n", error);
-                }
-            }
-            let status = format!(
-                "GUARDIAN SYSTEM STATUS\n---\nOSDP Online: {}\n{}Last Door Tick: {} seconds\n---",
-                PD_ONLINE.load(Ordering::SeqCst),
-                status_ip_info,
-                elapsed.as_secs(),
-            );
-            log::info!("{}", status);
-
-            // Prepare Heartbeat
-            let now = Instant::now();
-            if next_heartbeat < now {
-                next_heartbeat = now + HEARTBEAT_INTERVAL;
-                let is_healthy = elapsed.as_secs() < 2 && PD_ONLINE.load(Ordering::SeqCst);
-                let heartbeat = MANAGEReport::Heartbeat {
-                    is_healthy: is_healthy,
-                };
-                report_channel_tx.send(heartbeat).unwrap();
-            }
-        }
-    });
+    // Connect to MANAGE Door Security Websocket, reconnecting with backoff
+    let mut ws_client = ManageWsClient::new(
+        &config.ws_base_uri,
+        config.ws_timeout(),
+        command_router.clone(),
+    );
 
-    // Connect to MANAGE Door Security Websocket
-    let mut ws_client =
-        aperture_ws_client::ws_client_setup(WS_BASE_URI, WS_TIMEOUT, command_channel_tx.clone());
+    // Deliver reports to MANAGE, journaling what can't be sent
+    let mut manage_link = ManageLink::new(report_journal, controller_info);
 
     // Handle WebSocket Connection
     loop {
-        // Check if the WebSocket is closed
-        if !aperture_ws_client::WS_OPEN.load(std::sync::atomic::Ordering::SeqCst) {
-            log::warn!("WebSocket is closed! Reconnecting...");
+        manage_last_tick.store(Instant::now(), Ordering::SeqCst);
 
-            // Nuke the old WebSocket client (calls unsafe destroy method)
-            nuke_ws_client(&ws_client);
-
-            // Forget the old WebSocket client
-            mem::forget(ws_client);
-
-            // Create a new WebSocket client
-            ws_client = aperture_ws_client::ws_client_setup(
-                WS_BASE_URI,
-                WS_TIMEOUT,
-                command_channel_tx.clone(),
-            );
-        }
-
-        // Set next check time
-        let next_check = Instant::now() + Duration::from_secs(5);
+        // Create or tear down the WebSocket client as needed
+        ws_client.poll();
 
         // Send any reports
-        while let Ok(report) = report_channel_rx.recv_deadline(next_check) {
-            match ws_client.send(
-                FrameType::Text(false),
-                serde_json::to_string(&report).unwrap().as_bytes(),
-            ) {
-                Ok(_) => {
-                    log::info!("Sent report to MANAGE!: {:?}", report);
-                }
-                Err(_) => {
-                    log::error!("Failed to send report to MANAGE!: {:?}", report);
-                }
-            }
-        }
+        let next_check = Instant::now() + MANAGE_LOOP_INTERVAL;
+        manage_link.pump(&mut ws_client, &report_channel_rx, next_check);
     }
 }